@@ -0,0 +1,71 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Collected latency samples.
+#[derive(Debug, Default)]
+pub struct Latencies {
+    samples: Vec<Duration>,
+}
+
+impl Latencies {
+    pub fn record(&mut self, sample: Duration) {
+        self.samples.push(sample);
+    }
+
+    pub fn extend(&mut self, other: Latencies) {
+        self.samples.extend(other.samples);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Get the sample below which `percentile` percent of the samples fall.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut samples = self.samples.clone();
+        samples.sort_unstable();
+        let rank = (percentile / 100.0 * samples.len() as f64).ceil() as usize;
+        samples.get(rank.saturating_sub(1)).copied()
+    }
+}
+
+impl fmt::Display for Latencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |percentile| {
+            self.percentile(percentile)
+                .map_or("-".to_string(), |sample| {
+                    format!("{:.1}ms", sample.as_secs_f64() * 1000.0)
+                })
+        };
+        write!(
+            f,
+            "p50 {}  p90 {}  p99 {}  max {}  ({} samples)",
+            ms(50.0),
+            ms(90.0),
+            ms(99.0),
+            ms(100.0),
+            self.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_test() {
+        let mut latencies = Latencies::default();
+        assert_eq!(latencies.percentile(50.0), None);
+        for ms in (1..=100).rev() {
+            latencies.record(Duration::from_millis(ms));
+        }
+        assert_eq!(latencies.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(latencies.percentile(99.0), Some(Duration::from_millis(99)));
+        assert_eq!(
+            latencies.percentile(100.0),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(latencies.percentile(0.0), Some(Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,263 @@
+mod latency;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use latency::Latencies;
+use planc_client::{Client, ClientMessage, Encoding, VoteStatus};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Barrier;
+
+/// Command line arguments
+///
+/// The server limits connections, sessions and messages per client address. Start it with raised
+/// limits for benchmarks, e.g. `--max-sessions 100 --max-users 50 --connection-burst 10000
+/// --session-burst 1000 --message-burst 10000`.
+#[derive(Parser, Debug)]
+#[clap(version, author, about)]
+struct Args {
+    /// Websocket address of the planc server
+    #[clap(long, short = 's', default_value = "ws://localhost:8080")]
+    server: String,
+    /// Number of concurrent sessions
+    #[clap(long, default_value_t = 8)]
+    sessions: usize,
+    /// Number of voters in each session
+    #[clap(long, default_value_t = 16)]
+    users: usize,
+    /// Number of voting rounds in each session
+    #[clap(long, default_value_t = 10)]
+    rounds: usize,
+    /// Use MessagePack instead of JSON
+    #[clap(long)]
+    msgpack: bool,
+    /// Seconds after which a round, or joining the sessions, is considered failed
+    #[clap(long, default_value_t = 30)]
+    round_timeout: u64,
+}
+
+/// Counters shared by all voters.
+#[derive(Default)]
+struct Counters {
+    messages_sent: AtomicU64,
+    /// State updates received while waiting for votes and reveals.
+    states_received: AtomicU64,
+}
+
+/// Latencies observed by a single voter.
+#[derive(Default)]
+struct VoterReport {
+    /// Time from sending a vote until the own vote shows up in the state.
+    vote: Latencies,
+    /// Time from the last vote of a round until the reveal shows up in the state.
+    reveal: Latencies,
+}
+
+/// State shared by the voters of a session.
+struct SessionShared {
+    url: String,
+    users: usize,
+    /// Synchronizes the voters between phases of a round.
+    barrier: Barrier,
+    /// Time the last vote of the current round was sent.
+    last_vote: Mutex<Instant>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let encoding = if args.msgpack {
+        Encoding::MessagePack
+    } else {
+        Encoding::Json
+    };
+    let counters = Arc::new(Counters::default());
+
+    // Sessions get unique ids so repeated runs against the same server do not interfere.
+    let run_id = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis();
+    let mut voters = Vec::new();
+    for session in 0..args.sessions {
+        let shared = Arc::new(SessionShared {
+            url: format!(
+                "{}/api/bench-{}-{}",
+                args.server.trim_end_matches('/'),
+                run_id,
+                session
+            ),
+            users: args.users,
+            barrier: Barrier::new(args.users),
+            last_vote: Mutex::new(Instant::now()),
+        });
+        for index in 0..args.users {
+            let shared = Arc::clone(&shared);
+            let counters = Arc::clone(&counters);
+            let rounds = args.rounds;
+            let round_timeout = Duration::from_secs(args.round_timeout);
+            voters.push(tokio::spawn(async move {
+                voter(shared, counters, encoding, index, rounds, round_timeout)
+                    .await
+                    .with_context(|| format!("Voter {} of session {} failed", index, session))
+            }));
+        }
+    }
+
+    // The other voters of a session wait for a failed voter forever, so all voters are stopped on
+    // the first failure.
+    let start = Instant::now();
+    let abort_handles: Vec<_> = voters.iter().map(|voter| voter.abort_handle()).collect();
+    let reports =
+        futures::future::try_join_all(voters.into_iter().map(|voter| async move { voter.await? }))
+            .await;
+    let reports = match reports {
+        Ok(reports) => reports,
+        Err(err) => {
+            abort_handles.iter().for_each(|handle| handle.abort());
+            return Err(err);
+        }
+    };
+    let elapsed = start.elapsed();
+    let mut vote = Latencies::default();
+    let mut reveal = Latencies::default();
+    for report in reports {
+        vote.extend(report.vote);
+        reveal.extend(report.reveal);
+    }
+
+    let per_second = |count: u64| count as f64 / elapsed.as_secs_f64();
+    let messages_sent = counters.messages_sent.load(Ordering::Relaxed);
+    let states_received = counters.states_received.load(Ordering::Relaxed);
+    println!(
+        "{} sessions × {} users × {} rounds in {:.2}s",
+        args.sessions,
+        args.users,
+        args.rounds,
+        elapsed.as_secs_f64()
+    );
+    println!(
+        "messages sent    {} ({:.0}/s)",
+        messages_sent,
+        per_second(messages_sent)
+    );
+    println!(
+        "states received  {} ({:.0}/s)",
+        states_received,
+        per_second(states_received)
+    );
+    println!("vote latency     {}", vote);
+    println!("reveal latency   {}", reveal);
+    Ok(())
+}
+
+/// Join a session and vote in `rounds` rounds. The first voter claims the session and resets the
+/// points after every round.
+async fn voter(
+    shared: Arc<SessionShared>,
+    counters: Arc<Counters>,
+    encoding: Encoding,
+    index: usize,
+    rounds: usize,
+    round_timeout: Duration,
+) -> Result<VoterReport> {
+    let mut report = VoterReport::default();
+    let mut client = Client::connect(&shared.url, encoding).await?;
+    let user_id = client.whoami().await?;
+    send(
+        &mut client,
+        &counters,
+        ClientMessage::NameChange(format!("voter-{}", index)),
+    )
+    .await?;
+    if index == 0 {
+        send(&mut client, &counters, ClientMessage::ClaimSession).await?;
+    }
+    tokio::time::timeout(
+        round_timeout,
+        client.state_where(|state| state.users.len() == shared.users && state.admin.is_some()),
+    )
+    .await
+    .context("Joining the session timed out")??;
+
+    for round in 0..rounds {
+        tokio::time::timeout(
+            round_timeout,
+            play_round(
+                &shared,
+                &counters,
+                &mut client,
+                &mut report,
+                &user_id,
+                index,
+                round,
+            ),
+        )
+        .await
+        .with_context(|| format!("Round {} timed out", round))??;
+    }
+
+    // Let the other voters finish before leaving the session.
+    tokio::time::timeout(round_timeout, shared.barrier.wait())
+        .await
+        .context("Waiting for the other voters timed out")?;
+    let _ = client.close().await;
+    Ok(report)
+}
+
+/// Vote in a single round and wait for the reset of the points.
+async fn play_round(
+    shared: &SessionShared,
+    counters: &Counters,
+    client: &mut Client,
+    report: &mut VoterReport,
+    user_id: &str,
+    index: usize,
+    round: usize,
+) -> Result<()> {
+    shared.barrier.wait().await;
+    let sent_at = Instant::now();
+    {
+        let mut last_vote = shared.last_vote.lock().unwrap();
+        *last_vote = (*last_vote).max(sent_at);
+    }
+    let points = ((index + round) % 5).to_string();
+    send(client, counters, ClientMessage::SetPoints(points)).await?;
+
+    let mut voted = false;
+    loop {
+        let state = client.next_state().await?;
+        counters.states_received.fetch_add(1, Ordering::Relaxed);
+        let own_status = state.user(user_id).map(|user| &user.vote_status);
+        if !voted && own_status.is_some_and(|status| *status != VoteStatus::NotVoted) {
+            report.vote.record(sent_at.elapsed());
+            voted = true;
+        }
+        if state.revealed {
+            report
+                .reveal
+                .record(shared.last_vote.lock().unwrap().elapsed());
+            break;
+        }
+    }
+
+    shared.barrier.wait().await;
+    if index == 0 {
+        send(client, counters, ClientMessage::ResetPoints).await?;
+    }
+    client
+        .state_where(|state| {
+            !state.revealed
+                && state
+                    .users
+                    .values()
+                    .all(|user| user.vote_status == VoteStatus::NotVoted)
+        })
+        .await?;
+    Ok(())
+}
+
+async fn send(client: &mut Client, counters: &Counters, msg: ClientMessage) -> Result<()> {
+    counters.messages_sent.fetch_add(1, Ordering::Relaxed);
+    client.send(&msg).await
+}
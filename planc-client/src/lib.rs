@@ -0,0 +1,194 @@
+//! Client library for planc sessions.
+//!
+//! Connects to a planc server over a websocket and exchanges typed messages. Useful for scenario
+//! tests, bots and alternative frontends.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use planc_client::{Client, Encoding};
+//!
+//! let mut client = Client::connect("ws://localhost:8080/api/abcd", Encoding::Json).await?;
+//! let user_id = client.whoami().await?;
+//! client.set_name("alice").await?;
+//! client.set_points("5").await?;
+//! let state = client.state_where(|state| state.revealed).await?;
+//! println!("{} sees {:?}", user_id, state.users);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use planc_core::{
+    ClientMessage, Presence, ServerMessage, SessionSettings, SessionState, UserState, VoteStatus,
+};
+
+use anyhow::{anyhow, bail, Result};
+use futures::prelude::*;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Encoding of messages, negotiated as websocket subprotocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// JSON in text frames.
+    Json,
+    /// MessagePack in binary frames.
+    MessagePack,
+}
+
+impl Encoding {
+    fn protocol(self) -> &'static str {
+        match self {
+            Encoding::Json => "planc.json.v1",
+            Encoding::MessagePack => "planc.msgpack.v1",
+        }
+    }
+}
+
+/// A connection to a planc session.
+pub struct Client {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    encoding: Encoding,
+}
+
+impl Client {
+    /// Connect to a session, e.g. `ws://localhost:8080/api/<session_id>`.
+    pub async fn connect(url: &str, encoding: Encoding) -> Result<Self> {
+        let mut request = url.into_client_request()?;
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(encoding.protocol()),
+        );
+        let (socket, response) = tokio_tungstenite::connect_async(request).await?;
+        let protocol = response.headers().get("Sec-WebSocket-Protocol");
+        if protocol.and_then(|value| value.to_str().ok()) != Some(encoding.protocol()) {
+            bail!("Server did not accept subprotocol {}", encoding.protocol());
+        }
+        Ok(Self { socket, encoding })
+    }
+
+    pub async fn send(&mut self, msg: &ClientMessage) -> Result<()> {
+        let msg = match self.encoding {
+            Encoding::Json => Message::Text(Utf8Bytes::from(serde_json::to_string(msg)?)),
+            Encoding::MessagePack => Message::Binary(rmp_serde::to_vec_named(msg)?.into()),
+        };
+        self.socket.send(msg).await?;
+        Ok(())
+    }
+
+    /// Receive the next message. Returns `None` if the server closed the connection.
+    pub async fn recv(&mut self) -> Result<Option<ServerMessage>> {
+        while let Some(msg) = self.socket.next().await {
+            match (self.encoding, msg?) {
+                (Encoding::Json, Message::Text(text)) => {
+                    return Ok(Some(serde_json::from_str(&text)?))
+                }
+                (Encoding::MessagePack, Message::Binary(data)) => {
+                    return Ok(Some(rmp_serde::from_slice(&data)?))
+                }
+                (_, Message::Close(_)) => return Ok(None),
+                (_, Message::Ping(_)) | (_, Message::Pong(_)) | (_, Message::Frame(_)) => {}
+                (_, msg) => bail!("Unexpected frame: {:?}", msg),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Receive the next message that is not a keep-alive. Error messages and closed connections
+    /// are turned into errors.
+    async fn recv_relevant(&mut self) -> Result<ServerMessage> {
+        loop {
+            match self.recv().await? {
+                Some(ServerMessage::KeepAlive) => {}
+                Some(ServerMessage::Error(err)) => bail!("Server error: {}", err),
+                Some(msg) => return Ok(msg),
+                None => bail!("Connection closed"),
+            }
+        }
+    }
+
+    /// Request and return the id of this user.
+    pub async fn whoami(&mut self) -> Result<String> {
+        self.send(&ClientMessage::Whoami).await?;
+        loop {
+            if let ServerMessage::Whoami(user_id) = self.recv_relevant().await? {
+                return Ok(user_id);
+            }
+        }
+    }
+
+    /// Wait for the next state update.
+    pub async fn next_state(&mut self) -> Result<SessionState> {
+        loop {
+            if let ServerMessage::State(state) = self.recv_relevant().await? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Wait for a state matching `predicate`.
+    ///
+    /// The server may skip intermediate states, so wait for the expected state instead of
+    /// counting updates.
+    pub async fn state_where<F>(&mut self, predicate: F) -> Result<SessionState>
+    where
+        F: Fn(&SessionState) -> bool,
+    {
+        loop {
+            let state = self.next_state().await?;
+            if predicate(&state) {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Wait for an error message from the server.
+    pub async fn error(&mut self) -> Result<String> {
+        loop {
+            match self.recv().await? {
+                Some(ServerMessage::Error(err)) => return Ok(err),
+                Some(_) => {}
+                None => return Err(anyhow!("Connection closed")),
+            }
+        }
+    }
+
+    pub async fn set_name(&mut self, name: &str) -> Result<()> {
+        self.send(&ClientMessage::NameChange(name.to_string()))
+            .await
+    }
+
+    pub async fn set_points(&mut self, points: &str) -> Result<()> {
+        self.send(&ClientMessage::SetPoints(points.to_string()))
+            .await
+    }
+
+    pub async fn reset_points(&mut self) -> Result<()> {
+        self.send(&ClientMessage::ResetPoints).await
+    }
+
+    pub async fn claim_session(&mut self) -> Result<()> {
+        self.send(&ClientMessage::ClaimSession).await
+    }
+
+    pub async fn kick_user(&mut self, user_id: &str) -> Result<()> {
+        self.send(&ClientMessage::KickUser(user_id.to_string()))
+            .await
+    }
+
+    pub async fn set_spectator(&mut self, is_spectator: bool) -> Result<()> {
+        self.send(&ClientMessage::SetSpectator(is_spectator)).await
+    }
+
+    pub async fn set_settings(&mut self, settings: SessionSettings) -> Result<()> {
+        self.send(&ClientMessage::SetSettings(settings)).await
+    }
+
+    /// Close the connection.
+    pub async fn close(mut self) -> Result<()> {
+        self.socket.close(None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::transport::{MessageStream, Transport};
+use anyhow::Result;
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+
+/// A client connected to a session.
+pub struct Connection {
+    stream: MessageStream,
+    sender: Sender,
+}
+
+impl Connection {
+    pub fn new<T: Transport>(transport: T) -> Self {
+        let (stream, mut sink) = transport.split();
+
+        // Create channel to send messages.
+        let (channel, mut receiver) = mpsc::channel(0);
+        tokio::spawn(async move {
+            while let Some(msg) = receiver.next().await {
+                if let Err(err) = sink.send(msg).await {
+                    ::tracing::debug!(?err, "Connection/sink::send");
+                    break;
+                }
+            }
+        });
+
+        let sender = Sender { channel };
+        Self { stream, sender }
+    }
+
+    pub async fn send(&mut self, msg: ServerMessage) -> Result<()> {
+        self.sender.send(msg).await
+    }
+
+    pub fn sender(&self) -> Sender {
+        self.sender.clone()
+    }
+
+    pub async fn recv(&mut self) -> Option<Result<ClientMessage>> {
+        self.stream.next().await
+    }
+}
+
+/// Handle to send messages to a connection from other tasks.
+#[derive(Clone)]
+pub struct Sender {
+    channel: mpsc::Sender<ServerMessage>,
+}
+
+impl Sender {
+    pub async fn send(&mut self, msg: ServerMessage) -> Result<()> {
+        self.channel.send(msg).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use crate::connection::Connection;
+use crate::error::PlancError;
+use crate::protocol::ServerMessage;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::session::Session;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+/// Limits and timeouts of a service.
+#[derive(Debug, Clone)]
+pub struct ServiceContextConfig {
+    pub max_sessions: usize,
+    pub max_users: usize,
+    /// Time without messages after which a user is considered idle.
+    pub idle_timeout: Duration,
+    /// Time a disconnected user stays in the session before being removed. Disconnected users do
+    /// not count towards `max_users`.
+    pub disconnect_grace_period: Duration,
+    /// Limit for messages on a single connection.
+    pub message_rate_limit: RateLimit,
+    /// Limit for new connections per client address.
+    pub connection_rate_limit: RateLimit,
+    /// Limit for new sessions per client address.
+    pub session_rate_limit: RateLimit,
+}
+
+impl Default for ServiceContextConfig {
+    fn default() -> Self {
+        Self {
+            max_sessions: 8,
+            max_users: 16,
+            idle_timeout: Duration::from_secs(300),
+            disconnect_grace_period: Duration::from_secs(10),
+            message_rate_limit: RateLimit {
+                rate: 5.0,
+                burst: 20.0,
+            },
+            connection_rate_limit: RateLimit {
+                rate: 1.0,
+                burst: 10.0,
+            },
+            session_rate_limit: RateLimit {
+                rate: 0.1,
+                burst: 5.0,
+            },
+        }
+    }
+}
+
+/// Shared state of a service: all sessions and the per-client rate limiters.
+pub struct ServiceContext {
+    config: ServiceContextConfig,
+    sessions: Mutex<HashMap<String, Weak<Session>>>,
+    connection_limiter: RateLimiter<IpAddr>,
+    session_limiter: RateLimiter<IpAddr>,
+}
+
+impl ServiceContext {
+    pub fn new(config: ServiceContextConfig) -> Self {
+        let connection_limiter = RateLimiter::new(config.connection_rate_limit);
+        let session_limiter = RateLimiter::new(config.session_rate_limit);
+        Self {
+            config,
+            sessions: Mutex::default(),
+            connection_limiter,
+            session_limiter,
+        }
+    }
+
+    pub fn config(&self) -> &ServiceContextConfig {
+        &self.config
+    }
+
+    /// Check whether a client may open another connection.
+    pub fn check_connection_rate(&self, client_addr: IpAddr) -> Result<()> {
+        if self.connection_limiter.try_acquire(client_addr) {
+            Ok(())
+        } else {
+            Err(PlancError::RateLimited.into())
+        }
+    }
+
+    /// Get a pointer to a session.
+    ///
+    /// If the session does not exist it will be created, subject to the session rate limit of
+    /// `client_addr`.
+    pub fn get_session(
+        self: &Arc<Self>,
+        session_id: &str,
+        client_addr: IpAddr,
+    ) -> Result<Arc<Session>> {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        // Get session that already exists.
+        if let Some(weak_session) = sessions.get(session_id) {
+            if let Some(session) = weak_session.upgrade() {
+                return Ok(session);
+            } else {
+                sessions.remove(session_id);
+            }
+        }
+
+        // Check if maximum sessions would be exceeded.
+        if sessions.len() >= self.config.max_sessions {
+            return Err(PlancError::MaxSessionsExceeded.into());
+        }
+
+        // Check if the client creates sessions too quickly.
+        if !self.session_limiter.try_acquire(client_addr) {
+            return Err(PlancError::RateLimited.into());
+        }
+
+        // Create new session.
+        let session = Arc::new(Session::new(
+            self.clone(),
+            session_id,
+            self.config.max_users,
+        ));
+        sessions.insert(session_id.to_string(), Arc::downgrade(&session));
+        Ok(session)
+    }
+
+    /// Join a session or report why that is not possible to the client.
+    pub async fn join_session(
+        self: &Arc<Self>,
+        client_addr: IpAddr,
+        session_id: &str,
+        mut connection: Connection,
+    ) -> Result<()> {
+        match self.get_session(session_id, client_addr) {
+            Ok(session) => session.join(connection).await,
+            Err(err) => {
+                connection
+                    .send(ServerMessage::Error(format!(
+                        "Error joining session: {}",
+                        err
+                    )))
+                    .await
+            }
+        }
+    }
+
+    /// Cleanup weak references to a dropped session.
+    pub(crate) fn cleanup_session(&self, session_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        // It is possible that a session with the same id was already recreated at this point so we
+        // need to check that the reference in the session table is actually invalid.
+        if let Some(pointer) = sessions.get(session_id) {
+            if pointer.upgrade().is_none() {
+                sessions.remove(session_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_config;
+
+    #[test]
+    fn ctx_refcounting_test() {
+        let ctx = Arc::new(ServiceContext::new(test_config()));
+        assert_eq!(ctx.sessions.lock().unwrap().len(), 0);
+
+        let session = ctx.get_session("abcd", IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(ctx.sessions.lock().unwrap().len(), 1);
+        assert!(ctx.sessions.lock().unwrap().get("abcd").is_some());
+
+        std::mem::drop(session);
+        assert_eq!(ctx.sessions.lock().unwrap().len(), 0);
+    }
+}
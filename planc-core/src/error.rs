@@ -1,5 +1,6 @@
 use std::fmt;
 
+/// Errors reported to clients.
 #[derive(Debug, Clone, Copy)]
 pub enum PlancError {
     InvalidMessage,
@@ -9,6 +10,7 @@ pub enum PlancError {
     MaxUsersExceeded,
     UnknownUserId,
     UserKicked,
+    RateLimited,
 }
 
 impl fmt::Display for PlancError {
@@ -0,0 +1,25 @@
+//! Session logic of the planc planning poker server.
+//!
+//! A [`ServiceContext`] owns all sessions. Clients join a session with a [`Connection`] over any
+//! [`Transport`], e.g. a websocket or in-memory channels, and exchange [`ClientMessage`]s and
+//! [`ServerMessage`]s with it. HTTP handling is left to the embedding server.
+
+mod connection;
+mod context;
+mod error;
+mod protocol;
+mod rate_limit;
+mod session;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod transport;
+
+pub use self::connection::{Connection, Sender};
+pub use self::context::{ServiceContext, ServiceContextConfig};
+pub use self::error::PlancError;
+pub use self::protocol::{
+    ClientMessage, Presence, ServerMessage, SessionSettings, SessionState, UserState, VoteStatus,
+};
+pub use self::rate_limit::{RateLimit, RateLimiter, TokenBucket};
+pub use self::session::Session;
+pub use self::transport::{ChannelTransport, MessageSink, MessageStream, Transport};
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// State of a session as sent to clients.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionState {
+    /// Users by id. Serialized as a list ordered by join order so all clients render the same
+    /// order.
+    #[serde(
+        serialize_with = "serialize_users",
+        deserialize_with = "deserialize_users"
+    )]
+    pub users: HashMap<String, UserState>,
+    pub admin: Option<String>,
+    pub settings: SessionSettings,
+    /// Whether all users required to vote have voted and the points are revealed.
+    pub revealed: bool,
+    /// Number of votes per card. Only set after reveal if anonymous voting is enabled.
+    pub distribution: Option<BTreeMap<String, usize>>,
+}
+
+impl SessionState {
+    /// Get a user by id.
+    pub fn user(&self, user_id: &str) -> Option<&UserState> {
+        self.users.get(user_id)
+    }
+
+    /// Get all users ordered by join order.
+    pub fn ordered_users(&self) -> Vec<&UserState> {
+        let mut users: Vec<_> = self.users.values().collect();
+        users.sort_by_key(|user| user.join_order);
+        users
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SessionSettings {
+    /// Hide which user voted for which card after reveal.
+    pub anonymous_voting: bool,
+    /// Do not wait for idle or disconnected users before revealing the points.
+    pub exclude_idle_users: bool,
+}
+
+/// State of a user in a session.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserState {
+    pub id: String,
+    pub name: Option<String>,
+    /// The actual vote of this user. Never sent to clients directly, see `vote_status`.
+    #[serde(skip)]
+    pub points: Option<String>,
+    /// The vote of this user as seen by the receiving client.
+    pub vote_status: VoteStatus,
+    pub is_spectator: bool,
+    pub presence: Presence,
+    /// Position of this user in the user list.
+    pub join_order: u64,
+    /// Time the user joined the session in milliseconds since the unix epoch.
+    pub joined_at: u64,
+    /// Time of the last message from this user in milliseconds since the unix epoch.
+    pub last_active_at: u64,
+    #[serde(skip)]
+    pub kicked: bool,
+}
+
+fn serialize_users<S>(users: &HashMap<String, UserState>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut users: Vec<_> = users.values().collect();
+    users.sort_by_key(|user| user.join_order);
+    serializer.collect_seq(users)
+}
+
+fn deserialize_users<'de, D>(deserializer: D) -> Result<HashMap<String, UserState>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let users = Vec::<UserState>::deserialize(deserializer)?;
+    Ok(users
+        .into_iter()
+        .map(|user| (user.id.clone(), user))
+        .collect())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "tag", content = "content")]
+pub enum VoteStatus {
+    #[default]
+    NotVoted,
+    Voted,
+    Revealed(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Presence {
+    /// The user sent a message within the idle timeout.
+    #[default]
+    Active,
+    /// The user did not send a message within the idle timeout.
+    Idle,
+    /// The connection of the user is gone. The user is removed after a grace period.
+    Disconnected,
+}
+
+/// Messages sent by clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "tag", content = "content")]
+pub enum ClientMessage {
+    NameChange(String),
+    SetPoints(String),
+    ResetPoints,
+    Whoami,
+    ClaimSession,
+    KickUser(String),
+    SetSpectator(bool),
+    SetSettings(SessionSettings),
+}
+
+/// Messages sent to clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "tag", content = "content")]
+pub enum ServerMessage {
+    State(SessionState),
+    Whoami(String),
+    Error(String),
+    KeepAlive,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_state_serde_test() {
+        let mut state = SessionState::default();
+        for (id, join_order) in [("2", 2), ("10", 10), ("1", 1)] {
+            let user = UserState {
+                id: id.to_string(),
+                join_order,
+                points: Some("5".to_string()),
+                ..UserState::default()
+            };
+            state.users.insert(id.to_string(), user);
+        }
+
+        // Users are sent in join order and points are never sent.
+        let json = serde_json::to_value(&state).unwrap();
+        let ids: Vec<_> = json["users"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|user| user["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["1", "2", "10"]);
+        let state: SessionState = serde_json::from_value(json).unwrap();
+        assert_eq!(state.user("10").unwrap().join_order, 10);
+        assert!(state.user("10").unwrap().points.is_none());
+    }
+}
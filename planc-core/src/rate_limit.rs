@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Parameters of a token bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Tokens added per second.
+    pub rate: f64,
+    /// Maximum number of tokens, i.e. the number of requests allowed in a burst.
+    pub burst: f64,
+}
+
+/// Token bucket that limits a single client.
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token from the bucket. Returns `false` if the bucket is empty.
+    pub fn try_acquire(&mut self) -> bool {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.last_refill = now;
+    }
+
+    fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.limit.burst
+    }
+}
+
+/// Token buckets by key, e.g. by client address.
+pub struct RateLimiter<K> {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<K, TokenBucket>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    /// Number of buckets above which full buckets are discarded.
+    const PRUNE_THRESHOLD: usize = 1024;
+
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Take a token from the bucket of `key`. Returns `false` if the bucket is empty.
+    pub fn try_acquire(&self, key: K) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // Full buckets behave exactly like new ones so they can be dropped to bound memory usage.
+        if buckets.len() >= Self::PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| !bucket.is_full());
+        }
+
+        let limit = self.limit;
+        buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(limit))
+            .try_acquire()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_test() {
+        let limiter = RateLimiter::new(RateLimit {
+            rate: 0.0,
+            burst: 2.0,
+        });
+        assert!(limiter.try_acquire("a"));
+        assert!(limiter.try_acquire("a"));
+        assert!(!limiter.try_acquire("a"));
+        assert!(limiter.try_acquire("b"));
+    }
+}
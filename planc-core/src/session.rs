@@ -0,0 +1,688 @@
+use crate::connection::Connection;
+use crate::context::ServiceContext;
+use crate::error::PlancError;
+use crate::protocol::{
+    ClientMessage, Presence, ServerMessage, SessionState, UserState, VoteStatus,
+};
+use crate::rate_limit::TokenBucket;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio::sync::Mutex;
+use tracing::Instrument;
+
+/// A planning poker session that users join with a connection.
+pub struct Session {
+    ctx: Arc<ServiceContext>,
+    session_id: String,
+    max_users: usize,
+    session_state_tx: Mutex<watch::Sender<SessionState>>,
+    session_state_rx: watch::Receiver<SessionState>,
+    next_user_id: AtomicU64,
+}
+
+impl Session {
+    #[::tracing::instrument(skip(ctx), name = "Session::new")]
+    pub fn new(ctx: Arc<ServiceContext>, session_id: &str, max_users: usize) -> Self {
+        tracing::info!(session_id, "new_session");
+        let session_id = session_id.to_string();
+        let (session_state_tx, session_state_rx) = watch::channel(SessionState::default());
+        let session_state_tx = Mutex::new(session_state_tx);
+        let next_user_id = AtomicU64::new(1);
+        Self {
+            ctx,
+            session_id,
+            max_users,
+            session_state_tx,
+            session_state_rx,
+            next_user_id,
+        }
+    }
+
+    #[::tracing::instrument(skip(self, conn), name = "Session::user", fields(session_id = self.session_id, user_id))]
+    pub async fn join(&self, mut conn: Connection) -> Result<()> {
+        // Get a unique user id for this session. Ids are increasing so they double as join order.
+        let join_order = self
+            .next_user_id
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let user_id = join_order.to_string();
+        ::tracing::Span::current().record("user_id", &user_id);
+
+        // Add user to session state.
+        let add_user_result = self
+            .update_state(|mut state| {
+                // Disconnected users are leaving and do not take a place in the session.
+                let connected_users = state
+                    .users
+                    .values()
+                    .filter(|user| user.presence != Presence::Disconnected)
+                    .count();
+                if connected_users >= self.max_users {
+                    Err(PlancError::MaxUsersExceeded.into())
+                } else {
+                    let now = unix_millis();
+                    state.users.insert(
+                        user_id.clone(),
+                        UserState {
+                            id: user_id.clone(),
+                            join_order,
+                            joined_at: now,
+                            last_active_at: now,
+                            ..UserState::default()
+                        },
+                    );
+                    Result::Ok(state)
+                }
+            })
+            .await;
+        if let Err(err) = add_user_result {
+            ::tracing::warn!("join_denied");
+            conn.send(ServerMessage::Error(format!(
+                "Error joining session: {}",
+                err
+            )))
+            .await?;
+            return Ok(());
+        }
+        ::tracing::info!("joined");
+
+        // Subscribe client to state updates.
+        let mut sender = conn.sender();
+        let mut session_state_rx = self.session_state_rx.clone();
+        let cloned_user_id = user_id.clone();
+        tokio::spawn(
+            async move {
+                let user_id = cloned_user_id;
+                while session_state_rx.changed().await.is_ok() {
+                    // Clone state for some ad-hoc modifications and checks.
+                    let mut new_state = session_state_rx.borrow().to_owned();
+
+                    // Get a reference to this user's state or terminate this task.
+                    let user = if let Some(user) = new_state.users.get(&user_id) {
+                        user
+                    } else {
+                        break;
+                    };
+
+                    // Check if this user was kicked and stop this task if that is the case.
+                    if user.kicked {
+                        if let Err(err) = sender
+                            .send(ServerMessage::Error(
+                                "You have been kicked from the session".to_string(),
+                            ))
+                            .await
+                        {
+                            ::tracing::warn!(?err, "send_state_task/send_kick_message");
+                        }
+                        break;
+                    }
+
+                    // Mask kicked users.
+                    new_state.users.retain(|_, user| !user.kicked);
+
+                    // Mask points so they are not visible from the console. In anonymous mode the
+                    // points stay masked after reveal and only the distribution is sent.
+                    let exclude_idle_users = new_state.settings.exclude_idle_users;
+                    let revealed = new_state.users.values().any(|user| user.points.is_some())
+                        && !new_state.users.values().any(|user| {
+                            !user.is_spectator
+                                && user.points.is_none()
+                                && !(exclude_idle_users && user.presence != Presence::Active)
+                        });
+                    new_state.revealed = revealed;
+                    if revealed && new_state.settings.anonymous_voting {
+                        let mut distribution = BTreeMap::new();
+                        new_state
+                            .users
+                            .values()
+                            .filter(|user| !user.is_spectator)
+                            .filter_map(|user| user.points.clone())
+                            .for_each(|points| *distribution.entry(points).or_insert(0) += 1);
+                        new_state.distribution = Some(distribution);
+                    }
+                    let hide_points = !revealed || new_state.settings.anonymous_voting;
+                    for (item_user_id, item_user) in new_state.users.iter_mut() {
+                        item_user.vote_status = match &item_user.points {
+                            None => VoteStatus::NotVoted,
+                            Some(_) if hide_points && *item_user_id != user_id => VoteStatus::Voted,
+                            Some(points) => VoteStatus::Revealed(points.clone()),
+                        };
+                    }
+
+                    // Send the modified state.
+                    if let Err(err) = sender.send(ServerMessage::State(new_state)).await {
+                        ::tracing::warn!(?err, "send_state_task/send_state_message");
+                        break;
+                    }
+                }
+            }
+            .instrument(::tracing::Span::current()),
+        );
+
+        // Send a keep-alive message every 5 seconds. This may be necessary to keep the websocket
+        // connection alive when certain reverse proxies are used.
+        let mut sender = conn.sender();
+        tokio::spawn(
+            async move {
+                while sender.send(ServerMessage::KeepAlive).await.is_ok() {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+            .instrument(::tracing::Span::current()),
+        );
+
+        // Listen to messages from the connection while watching for inactivity.
+        let result = tokio::select! {
+            result = self.handle_connection(conn, &user_id) => result,
+            result = self.watch_idle(&user_id) => result,
+        };
+        if let Err(err) = result {
+            ::tracing::warn!(?err, "handle_connection");
+        }
+
+        // Keep disconnected users around for a grace period so other participants can see that
+        // the connection dropped instead of the user silently vanishing. They give up the admin
+        // role right away and are replaced if a client reconnects with the same name. Kicked users
+        // are removed immediately.
+        let disconnect_grace_period = self.ctx.config().disconnect_grace_period;
+        let kicked = self
+            .user_state(&user_id)
+            .await
+            .map(|user_state| user_state.kicked)
+            .unwrap_or(true);
+        if !kicked && !disconnect_grace_period.is_zero() {
+            self.update_state(|mut state| {
+                if let Some(user_state) = state.users.get_mut(&user_id) {
+                    user_state.presence = Presence::Disconnected;
+                }
+                if state.admin.as_ref() == Some(&user_id) {
+                    state.admin = None;
+                }
+                Result::Ok(state)
+            })
+            .await?;
+            tokio::time::sleep(disconnect_grace_period).await;
+        }
+
+        // Remove user from state.
+        self.update_state(|mut state| {
+            state.users.remove(&user_id);
+            if state.admin.as_ref() == Some(&user_id) {
+                state.admin = None;
+            }
+            Result::Ok(state)
+        })
+        .await?;
+
+        ::tracing::info!("user_leaving");
+        Ok(())
+    }
+
+    async fn handle_connection(&self, mut conn: Connection, user_id: &str) -> Result<()> {
+        let mut rate_limit = TokenBucket::new(self.ctx.config().message_rate_limit);
+        while let Some(msg) = conn.recv().await {
+            // Every message may trigger a broadcast to all users so flooding is not tolerated.
+            if !rate_limit.try_acquire() {
+                ::tracing::warn!("message_rate_limited");
+                conn.send(ServerMessage::Error(PlancError::RateLimited.to_string()))
+                    .await?;
+                return Err(PlancError::RateLimited.into());
+            }
+
+            // Terminate the connection for kicked users.
+            let user_state = self.user_state(user_id).await?;
+            if user_state.kicked {
+                return Err(PlancError::UserKicked.into());
+            }
+
+            let result = match msg? {
+                ClientMessage::NameChange(name) if name.len() <= 32 => {
+                    self.update_state(|mut state| {
+                        // A reconnecting client takes the place of its disconnected user.
+                        state.users.retain(|_, user| {
+                            user.presence != Presence::Disconnected
+                                || user.name.as_ref() != Some(&name)
+                        });
+                        if state
+                            .users
+                            .values()
+                            .all(|user| user.name.as_ref() != Some(&name))
+                        {
+                            state.users.get_mut(user_id).unwrap().name = Some(name.clone());
+                            Ok(state)
+                        } else {
+                            Err(PlancError::DuplicateName.into())
+                        }
+                    })
+                    .await
+                }
+                ClientMessage::SetPoints(points) if points.len() <= 8 => {
+                    self.update_state(|mut state| {
+                        let user_state = state.users.get_mut(user_id).unwrap();
+                        if !user_state.is_spectator {
+                            user_state.points = Some(points.clone());
+                            Ok(state)
+                        } else {
+                            Err(PlancError::InvalidMessage.into())
+                        }
+                    })
+                    .await
+                }
+                ClientMessage::ResetPoints => {
+                    self.update_state(|mut state| {
+                        if state.admin.as_deref() == Some(user_id) {
+                            for user in state.users.values_mut() {
+                                user.points = None;
+                            }
+                            Ok(state)
+                        } else {
+                            Err(PlancError::InsufficientPermissions.into())
+                        }
+                    })
+                    .await
+                }
+                ClientMessage::Whoami => {
+                    conn.send(ServerMessage::Whoami(user_id.to_string())).await
+                }
+                ClientMessage::ClaimSession => {
+                    self.update_state(|mut state| {
+                        if state.admin.is_none() {
+                            state.admin = Some(user_id.to_string());
+                            ::tracing::info!("claiming_session");
+                            Ok(state)
+                        } else {
+                            Err(PlancError::InsufficientPermissions.into())
+                        }
+                    })
+                    .await
+                }
+                ClientMessage::KickUser(kickee_id) => {
+                    self.update_state(|mut state| {
+                        if state.admin.as_deref() == Some(user_id) {
+                            if let Some(kickee) = state.users.get_mut(&kickee_id) {
+                                kickee.kicked = true;
+                                ::tracing::info!(kickee_id, "kicking_user");
+                                Ok(state)
+                            } else {
+                                Err(PlancError::UnknownUserId.into())
+                            }
+                        } else {
+                            Err(PlancError::InsufficientPermissions.into())
+                        }
+                    })
+                    .await
+                }
+                ClientMessage::SetSpectator(is_spectator) => {
+                    self.update_state(|mut state| {
+                        let user_state = state.users.get_mut(user_id).unwrap();
+                        user_state.is_spectator = is_spectator;
+                        user_state.points = None;
+                        Ok(state)
+                    })
+                    .await
+                }
+                ClientMessage::SetSettings(settings) => {
+                    self.update_state(|mut state| {
+                        if state.admin.as_deref() == Some(user_id) {
+                            ::tracing::info!(?settings, "changing_settings");
+                            state.settings = settings.clone();
+                            Ok(state)
+                        } else {
+                            Err(PlancError::InsufficientPermissions.into())
+                        }
+                    })
+                    .await
+                }
+                _ => Err(PlancError::InvalidMessage.into()),
+            };
+            if let Err(err) = result {
+                conn.send(ServerMessage::Error(err.to_string())).await?;
+                return Err(err);
+            }
+            self.touch_user(user_id).await;
+        }
+        Ok(())
+    }
+
+    async fn update_state<F>(&self, mut func: F) -> Result<()>
+    where
+        F: FnMut(SessionState) -> Result<SessionState>,
+    {
+        let session_state_tx = self.session_state_tx.lock().await;
+        let current_state = session_state_tx.borrow().clone();
+        let new_state = func(current_state)?;
+        session_state_tx.send(new_state).unwrap();
+        Ok(())
+    }
+
+    /// Record activity of a user.
+    ///
+    /// This only notifies subscribers if the user was idle before. Otherwise the new timestamp is
+    /// sent with the next state update, which avoids an additional broadcast for every message.
+    async fn touch_user(&self, user_id: &str) {
+        let session_state_tx = self.session_state_tx.lock().await;
+        session_state_tx.send_if_modified(|state| {
+            if let Some(user_state) = state.users.get_mut(user_id) {
+                user_state.last_active_at = unix_millis();
+                if user_state.presence != Presence::Active {
+                    user_state.presence = Presence::Active;
+                    return true;
+                }
+            }
+            false
+        });
+    }
+
+    /// Periodically mark the user as idle if there was no activity within the idle timeout.
+    ///
+    /// Only returns if the user does not exist anymore.
+    async fn watch_idle(&self, user_id: &str) -> Result<()> {
+        let idle_timeout = self.ctx.config().idle_timeout.as_millis() as u64;
+        loop {
+            let last_active_at = {
+                let session_state_tx = self.session_state_tx.lock().await;
+                let mut last_active_at = None;
+                session_state_tx.send_if_modified(|state| {
+                    let user_state = match state.users.get_mut(user_id) {
+                        Some(user_state) => user_state,
+                        None => return false,
+                    };
+                    last_active_at = Some(user_state.last_active_at);
+                    if user_state.presence == Presence::Active
+                        && unix_millis() >= user_state.last_active_at + idle_timeout
+                    {
+                        ::tracing::info!("user_idle");
+                        user_state.presence = Presence::Idle;
+                        true
+                    } else {
+                        false
+                    }
+                });
+                last_active_at.ok_or(PlancError::UnknownUserId)?
+            };
+
+            // Sleep until the user may become idle, but at least a second.
+            let idle_at = last_active_at + idle_timeout;
+            let sleep_millis = idle_at.saturating_sub(unix_millis()).max(1000);
+            tokio::time::sleep(Duration::from_millis(sleep_millis)).await;
+        }
+    }
+
+    async fn user_state(&self, user_id: &str) -> Result<UserState> {
+        let session_state_tx = self.session_state_tx.lock().await;
+        let current_state = session_state_tx.borrow();
+        if let Some(user_state) = current_state.users.get(user_id) {
+            Ok(user_state.clone())
+        } else {
+            Err(PlancError::UnknownUserId.into())
+        }
+    }
+}
+
+impl Drop for Session {
+    #[tracing::instrument(skip(self), name = "Session::drop", fields(session_id = self.session_id))]
+    fn drop(&mut self) {
+        ::tracing::info!("Session::drop: Removing session \"{}\"", self.session_id);
+        self.ctx.cleanup_session(&self.session_id);
+    }
+}
+
+/// Current time in milliseconds since the unix epoch.
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::ServiceContextConfig;
+    use crate::protocol::SessionSettings;
+    use crate::rate_limit::RateLimit;
+    use crate::testing::{test_config, TestClient};
+
+    fn user<'a>(state: &'a SessionState, user_id: &str) -> Option<&'a UserState> {
+        state.users.get(user_id)
+    }
+
+    #[tokio::test]
+    async fn voting_test() {
+        let ctx = Arc::new(ServiceContext::new(test_config()));
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let mut bob = TestClient::join(&ctx, "abcd", "bob").await;
+        let mut carol = TestClient::join(&ctx, "abcd", "carol").await;
+        let (alice_id, bob_id) = (alice.user_id.clone(), bob.user_id.clone());
+        carol.send(ClientMessage::SetSpectator(true)).await;
+
+        // Points of other users are masked until everybody has voted.
+        alice.send(ClientMessage::SetPoints("5".to_string())).await;
+        let state = bob
+            .state_where(|state| {
+                user(state, &alice_id).unwrap().vote_status != VoteStatus::NotVoted
+            })
+            .await;
+        assert_eq!(
+            user(&state, &alice_id).unwrap().vote_status,
+            VoteStatus::Voted
+        );
+        assert!(!state.revealed);
+        let state = alice
+            .state_where(|state| {
+                user(state, &alice_id).unwrap().vote_status != VoteStatus::NotVoted
+            })
+            .await;
+        assert_eq!(
+            user(&state, &alice_id).unwrap().vote_status,
+            VoteStatus::Revealed("5".to_string())
+        );
+
+        // Spectators do not have to vote.
+        bob.send(ClientMessage::SetPoints("8".to_string())).await;
+        let state = carol.state_where(|state| state.revealed).await;
+        assert_eq!(
+            user(&state, &alice_id).unwrap().vote_status,
+            VoteStatus::Revealed("5".to_string())
+        );
+        assert_eq!(
+            user(&state, &bob_id).unwrap().vote_status,
+            VoteStatus::Revealed("8".to_string())
+        );
+        assert!(state.distribution.is_none());
+    }
+
+    #[tokio::test]
+    async fn anonymous_voting_test() {
+        let ctx = Arc::new(ServiceContext::new(test_config()));
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let mut bob = TestClient::join(&ctx, "abcd", "bob").await;
+        let (alice_id, bob_id) = (alice.user_id.clone(), bob.user_id.clone());
+        alice.send(ClientMessage::ClaimSession).await;
+        alice
+            .send(ClientMessage::SetSettings(SessionSettings {
+                anonymous_voting: true,
+                ..SessionSettings::default()
+            }))
+            .await;
+        bob.state_where(|state| state.settings.anonymous_voting)
+            .await;
+
+        alice.send(ClientMessage::SetPoints("5".to_string())).await;
+        bob.send(ClientMessage::SetPoints("5".to_string())).await;
+        let state = bob.state_where(|state| state.revealed).await;
+
+        // Only the own vote and the distribution are visible after reveal.
+        assert_eq!(
+            user(&state, &alice_id).unwrap().vote_status,
+            VoteStatus::Voted
+        );
+        assert_eq!(
+            user(&state, &bob_id).unwrap().vote_status,
+            VoteStatus::Revealed("5".to_string())
+        );
+        let distribution = state.distribution.unwrap();
+        assert_eq!(distribution.len(), 1);
+        assert_eq!(distribution["5"], 2);
+    }
+
+    #[tokio::test]
+    async fn kick_test() {
+        let ctx = Arc::new(ServiceContext::new(test_config()));
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let mut bob = TestClient::join(&ctx, "abcd", "bob").await;
+        let (alice_id, bob_id) = (alice.user_id.clone(), bob.user_id.clone());
+        alice.send(ClientMessage::ClaimSession).await;
+        alice
+            .state_where(|state| state.admin.as_ref() == Some(&alice_id))
+            .await;
+
+        alice.send(ClientMessage::KickUser(bob_id.clone())).await;
+        assert!(bob.error().await.contains("kicked"));
+        alice
+            .state_where(|state| user(state, &bob_id).is_none())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn permissions_test() {
+        let ctx = Arc::new(ServiceContext::new(test_config()));
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let alice_id = alice.user_id.clone();
+        let mut bob = TestClient::join(&ctx, "abcd", "bob").await;
+
+        // Only admins can kick users and reset points.
+        bob.send(ClientMessage::KickUser(alice_id.clone())).await;
+        assert_eq!(bob.error().await, "InsufficientPermissions");
+        let mut bob = TestClient::join(&ctx, "abcd", "bob").await;
+        bob.send(ClientMessage::ResetPoints).await;
+        assert_eq!(bob.error().await, "InsufficientPermissions");
+        alice.state_where(|state| state.users.len() == 1).await;
+    }
+
+    #[tokio::test]
+    async fn admin_succession_test() {
+        let ctx = Arc::new(ServiceContext::new(test_config()));
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let mut bob = TestClient::join(&ctx, "abcd", "bob").await;
+        let alice_id = alice.user_id.clone();
+        alice.send(ClientMessage::ClaimSession).await;
+        bob.state_where(|state| state.admin.as_ref() == Some(&alice_id))
+            .await;
+
+        // The session can only be claimed while there is no admin.
+        let mut carol = TestClient::join(&ctx, "abcd", "carol").await;
+        carol.send(ClientMessage::ClaimSession).await;
+        assert_eq!(carol.error().await, "InsufficientPermissions");
+
+        // The admin role is released when the admin leaves.
+        std::mem::drop(alice);
+        bob.state_where(|state| state.admin.is_none()).await;
+        let bob_id = bob.user_id.clone();
+        bob.send(ClientMessage::ClaimSession).await;
+        bob.state_where(|state| state.admin.as_ref() == Some(&bob_id))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn max_users_test() {
+        let ctx = Arc::new(ServiceContext::new(ServiceContextConfig {
+            max_users: 2,
+            ..test_config()
+        }));
+        let _alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let _bob = TestClient::join(&ctx, "abcd", "bob").await;
+        let mut carol = TestClient::connect(&ctx, "abcd");
+        assert!(carol.error().await.contains("MaxUsersExceeded"));
+    }
+
+    #[tokio::test]
+    async fn disconnect_test() {
+        let ctx = Arc::new(ServiceContext::new(ServiceContextConfig {
+            max_users: 2,
+            disconnect_grace_period: Duration::from_secs(60),
+            ..test_config()
+        }));
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let mut bob = TestClient::join(&ctx, "abcd", "bob").await;
+        let alice_id = alice.user_id.clone();
+        alice.send(ClientMessage::ClaimSession).await;
+        bob.state_where(|state| state.admin.as_ref() == Some(&alice_id))
+            .await;
+
+        // Disconnected users give up the admin role.
+        std::mem::drop(alice);
+        let state = bob
+            .state_where(|state| {
+                user(state, &alice_id).map(|user| &user.presence) == Some(&Presence::Disconnected)
+            })
+            .await;
+        assert_eq!(state.admin, None);
+
+        // A reconnecting client fits in the session and replaces its disconnected user.
+        let alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let state = bob
+            .state_where(|state| user(state, &alice_id).is_none())
+            .await;
+        assert!(user(&state, &alice.user_id).is_some());
+        assert_eq!(state.users.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn max_sessions_test() {
+        let ctx = Arc::new(ServiceContext::new(ServiceContextConfig {
+            max_sessions: 1,
+            ..test_config()
+        }));
+        let _alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let mut bob = TestClient::connect(&ctx, "efgh");
+        assert!(bob.error().await.contains("MaxSessionsExceeded"));
+    }
+
+    #[tokio::test]
+    async fn message_rate_limit_test() {
+        let ctx = Arc::new(ServiceContext::new(ServiceContextConfig {
+            message_rate_limit: RateLimit {
+                rate: 0.0,
+                burst: 4.0,
+            },
+            ..test_config()
+        }));
+        // Joining takes two messages.
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        for _ in 0..3 {
+            alice.send(ClientMessage::Whoami).await;
+        }
+        assert_eq!(alice.error().await, "RateLimited");
+    }
+
+    #[tokio::test]
+    async fn exclude_idle_users_test() {
+        let ctx = Arc::new(ServiceContext::new(ServiceContextConfig {
+            idle_timeout: Duration::from_millis(100),
+            ..test_config()
+        }));
+        let mut alice = TestClient::join(&ctx, "abcd", "alice").await;
+        let bob = TestClient::join(&ctx, "abcd", "bob").await;
+        let bob_id = bob.user_id.clone();
+        alice.send(ClientMessage::ClaimSession).await;
+        alice
+            .send(ClientMessage::SetSettings(SessionSettings {
+                exclude_idle_users: true,
+                ..SessionSettings::default()
+            }))
+            .await;
+        alice.send(ClientMessage::SetPoints("3".to_string())).await;
+
+        // Bob never votes but the points are revealed once he is idle.
+        let state = alice.state_where(|state| state.revealed).await;
+        assert_eq!(user(&state, &bob_id).unwrap().presence, Presence::Idle);
+        assert_eq!(
+            user(&state, &bob_id).unwrap().vote_status,
+            VoteStatus::NotVoted
+        );
+    }
+}
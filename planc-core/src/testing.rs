@@ -0,0 +1,121 @@
+//! Test harness to drive sessions with in-memory clients.
+
+use crate::connection::Connection;
+use crate::context::{ServiceContext, ServiceContextConfig};
+use crate::protocol::{ClientMessage, ServerMessage, SessionState};
+use crate::transport::ChannelTransport;
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Time to wait for an expected message before failing a test.
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Service configuration for tests.
+///
+/// Disconnected users are removed immediately so tests can observe users leaving.
+pub fn test_config() -> ServiceContextConfig {
+    ServiceContextConfig {
+        max_sessions: 16,
+        max_users: 8,
+        disconnect_grace_period: Duration::ZERO,
+        ..ServiceContextConfig::default()
+    }
+}
+
+/// A client connected to a session over in-memory channels.
+///
+/// The client leaves the session when it is dropped.
+pub struct TestClient {
+    sender: mpsc::Sender<ClientMessage>,
+    receiver: mpsc::Receiver<ServerMessage>,
+    pub user_id: String,
+}
+
+impl TestClient {
+    /// Connect to a session without waiting for the join to succeed.
+    pub fn connect(ctx: &Arc<ServiceContext>, session_id: &str) -> Self {
+        let (sender, incoming) = mpsc::channel(16);
+        let (outgoing, receiver) = mpsc::channel(16);
+        let connection = Connection::new(ChannelTransport::new(incoming, outgoing));
+        let ctx = Arc::clone(ctx);
+        let session_id = session_id.to_string();
+        tokio::spawn(async move {
+            let client_addr = IpAddr::from([127, 0, 0, 1]);
+            let _ = ctx.join_session(client_addr, &session_id, connection).await;
+        });
+        Self {
+            sender,
+            receiver,
+            user_id: String::new(),
+        }
+    }
+
+    /// Join a session with a name.
+    pub async fn join(ctx: &Arc<ServiceContext>, session_id: &str, name: &str) -> Self {
+        let mut client = Self::connect(ctx, session_id);
+        client.send(ClientMessage::Whoami).await;
+        client.user_id = loop {
+            if let ServerMessage::Whoami(user_id) = client.recv().await {
+                break user_id;
+            }
+        };
+        client
+            .send(ClientMessage::NameChange(name.to_string()))
+            .await;
+        client
+            .state_where(|state| {
+                state
+                    .users
+                    .values()
+                    .any(|user| user.name.as_deref() == Some(name))
+            })
+            .await;
+        client
+    }
+
+    pub async fn send(&mut self, msg: ClientMessage) {
+        self.sender.send(msg).await.expect("Client disconnected");
+    }
+
+    /// Receive the next message that is not a keep-alive.
+    pub async fn recv(&mut self) -> ServerMessage {
+        loop {
+            let msg = tokio::time::timeout(RECV_TIMEOUT, self.receiver.next())
+                .await
+                .expect("Timeout waiting for message")
+                .expect("Connection closed");
+            if !matches!(msg, ServerMessage::KeepAlive) {
+                return msg;
+            }
+        }
+    }
+
+    /// Wait for a state matching `predicate`.
+    ///
+    /// Intermediate states may be skipped by the server, so tests should wait for the state they
+    /// expect instead of counting updates.
+    pub async fn state_where<F>(&mut self, predicate: F) -> SessionState
+    where
+        F: Fn(&SessionState) -> bool,
+    {
+        loop {
+            match self.recv().await {
+                ServerMessage::State(state) if predicate(&state) => return state,
+                ServerMessage::Error(err) => panic!("Unexpected error: {}", err),
+                _ => {}
+            }
+        }
+    }
+
+    /// Wait for an error message.
+    pub async fn error(&mut self) -> String {
+        loop {
+            if let ServerMessage::Error(err) = self.recv().await {
+                return err;
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::protocol::{ClientMessage, ServerMessage};
+use anyhow::{Error, Result};
+use futures::channel::mpsc;
+use futures::stream::BoxStream;
+use futures::{Sink, SinkExt, StreamExt};
+use std::pin::Pin;
+
+/// Stream of messages received from a client.
+pub type MessageStream = BoxStream<'static, Result<ClientMessage>>;
+
+/// Sink for messages sent to a client.
+pub type MessageSink = Pin<Box<dyn Sink<ServerMessage, Error = Error> + Send>>;
+
+/// A way to exchange messages with a client.
+///
+/// Transports take care of encoding and liveness. The incoming stream ends when the client is
+/// gone.
+pub trait Transport: Send + 'static {
+    fn split(self) -> (MessageStream, MessageSink);
+}
+
+/// Transport over in-memory channels.
+///
+/// Useful to drive sessions from tests or from frontends running in the same process.
+pub struct ChannelTransport {
+    incoming: mpsc::Receiver<ClientMessage>,
+    outgoing: mpsc::Sender<ServerMessage>,
+}
+
+impl ChannelTransport {
+    /// Create a transport receiving from `incoming` and sending to `outgoing`.
+    ///
+    /// The client leaves when the sending half of `incoming` is dropped.
+    pub fn new(
+        incoming: mpsc::Receiver<ClientMessage>,
+        outgoing: mpsc::Sender<ServerMessage>,
+    ) -> Self {
+        Self { incoming, outgoing }
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn split(self) -> (MessageStream, MessageSink) {
+        let stream = self.incoming.map(Ok).boxed();
+        let sink = Box::pin(self.outgoing.sink_err_into());
+        (stream, sink)
+    }
+}
@@ -0,0 +1,109 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use planc_client::{ClientMessage, ServerMessage, SessionSettings, SessionState, VoteStatus};
+
+/// State of the terminal client.
+pub struct App {
+    pub session_id: String,
+    pub user_id: String,
+    /// Card values of the active deck.
+    pub cards: Vec<String>,
+    /// Index of the highlighted card.
+    pub selected: usize,
+    pub state: Option<SessionState>,
+    /// Last error reported by the server.
+    pub error: Option<String>,
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(session_id: String, user_id: String, cards: Vec<String>) -> Self {
+        Self {
+            session_id,
+            user_id,
+            cards,
+            selected: 0,
+            state: None,
+            error: None,
+            quit: false,
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        let state = self.state.as_ref();
+        state.and_then(|state| state.admin.as_deref()) == Some(self.user_id.as_str())
+    }
+
+    /// Whether the session can be claimed, which is only possible while it has no admin.
+    pub fn can_claim(&self) -> bool {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.admin.is_none())
+    }
+
+    /// Whether the user can pick a card, i.e. participates and has not voted yet.
+    pub fn can_vote(&self) -> bool {
+        let state = self.state.as_ref();
+        match state.and_then(|state| state.user(&self.user_id)) {
+            Some(user) => !user.is_spectator && user.vote_status == VoteStatus::NotVoted,
+            None => false,
+        }
+    }
+
+    pub fn handle_message(&mut self, msg: ServerMessage) {
+        match msg {
+            ServerMessage::State(state) => self.state = Some(state),
+            ServerMessage::Error(err) => self.error = Some(err),
+            ServerMessage::Whoami(user_id) => self.user_id = user_id,
+            ServerMessage::KeepAlive => {}
+        }
+    }
+
+    /// Handle a key press. Returns the message to send to the server, if any.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<ClientMessage> {
+        self.error = None;
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.quit = true;
+                None
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.quit = true;
+                None
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.selected = self.selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.selected = (self.selected + 1).min(self.cards.len().saturating_sub(1));
+                None
+            }
+            KeyCode::Enter | KeyCode::Char(' ') if self.can_vote() => {
+                let points = self.cards.get(self.selected)?;
+                Some(ClientMessage::SetPoints(points.clone()))
+            }
+            KeyCode::Char('s') => {
+                let state = self.state.as_ref()?;
+                let user = state.user(&self.user_id)?;
+                Some(ClientMessage::SetSpectator(!user.is_spectator))
+            }
+            KeyCode::Char('c') if self.can_claim() => Some(ClientMessage::ClaimSession),
+            KeyCode::Char('r') if self.is_admin() => Some(ClientMessage::ResetPoints),
+            KeyCode::Char('a') if self.is_admin() => {
+                let settings = &self.state.as_ref()?.settings;
+                Some(ClientMessage::SetSettings(SessionSettings {
+                    anonymous_voting: !settings.anonymous_voting,
+                    ..settings.clone()
+                }))
+            }
+            KeyCode::Char('i') if self.is_admin() => {
+                let settings = &self.state.as_ref()?.settings;
+                Some(ClientMessage::SetSettings(SessionSettings {
+                    exclude_idle_users: !settings.exclude_idle_users,
+                    ..settings.clone()
+                }))
+            }
+            _ => None,
+        }
+    }
+}
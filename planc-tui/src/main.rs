@@ -0,0 +1,76 @@
+mod app;
+mod statistics;
+mod ui;
+
+use anyhow::Result;
+use app::App;
+use clap::Parser;
+use crossterm::event::{Event, EventStream, KeyEventKind};
+use futures::prelude::*;
+use planc_client::{Client, Encoding};
+use ratatui::DefaultTerminal;
+
+/// Card values of the default deck, same as in the web frontend.
+const CARD_VALUES: &str = "0,1,2,3,5,8,13,20,40,60,100,?,☕";
+
+/// Command line arguments
+#[derive(Parser, Debug)]
+#[clap(version, author, about)]
+struct Args {
+    /// Session to join
+    session_id: String,
+    /// Name shown to other users
+    #[clap(long, short = 'n')]
+    name: String,
+    /// Websocket address of the planc server
+    #[clap(long, short = 's', default_value = "ws://localhost:8080")]
+    server: String,
+    /// Card values to pick from
+    #[clap(long, default_value = CARD_VALUES, value_delimiter = ',')]
+    cards: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let url = format!(
+        "{}/api/{}",
+        args.server.trim_end_matches('/'),
+        args.session_id
+    );
+    let mut client = Client::connect(&url, Encoding::Json).await?;
+    let user_id = client.whoami().await?;
+    client.set_name(&args.name).await?;
+    let mut app = App::new(args.session_id, user_id, args.cards);
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut client, &mut app).await;
+    ratatui::restore();
+    result
+}
+
+/// Draw the application and process server messages and key presses until the user quits.
+async fn run(terminal: &mut DefaultTerminal, client: &mut Client, app: &mut App) -> Result<()> {
+    let mut events = EventStream::new();
+    while !app.quit {
+        terminal.draw(|frame| ui::render(frame, app))?;
+        tokio::select! {
+            msg = client.recv() => match msg? {
+                Some(msg) => app.handle_message(msg),
+                None => anyhow::bail!("Connection closed by server"),
+            },
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    if let Some(msg) = app.handle_key(key) {
+                        client.send(&msg).await?;
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+                None => break,
+            },
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use planc_client::{SessionState, VoteStatus};
+
+/// Statistics about the revealed votes of a round.
+#[derive(Debug, PartialEq)]
+pub struct Statistics {
+    pub mean_vote: Option<f64>,
+    pub low_vote: Option<f64>,
+    pub high_vote: Option<f64>,
+    /// Users who voted the lowest points. Empty in anonymous mode.
+    pub low_voters: Vec<String>,
+    /// Users who voted the highest points. Empty in anonymous mode.
+    pub high_voters: Vec<String>,
+    /// Number of votes that are not a number, e.g. "?".
+    pub votes_excluded: usize,
+}
+
+impl Statistics {
+    /// Compute statistics of a revealed round. Returns `None` if the votes are not revealed yet.
+    pub fn from_state(state: &SessionState) -> Option<Self> {
+        if !state.revealed {
+            return None;
+        }
+
+        // In anonymous mode the server only sends the vote distribution.
+        let votes: Vec<(Option<&str>, usize)> = match &state.distribution {
+            Some(distribution) => distribution
+                .iter()
+                .map(|(points, count)| (Some(points.as_str()), *count))
+                .collect(),
+            None => state
+                .users
+                .values()
+                .filter(|user| !user.is_spectator)
+                .map(|user| (revealed_points(&user.vote_status), 1))
+                .collect(),
+        };
+
+        let mut sum = 0.0;
+        let mut count = 0;
+        let mut low_vote: Option<f64> = None;
+        let mut high_vote: Option<f64> = None;
+        let mut votes_excluded = 0;
+        for (vote, n) in votes {
+            match vote.and_then(parse_points) {
+                Some(points) => {
+                    sum += points * n as f64;
+                    count += n;
+                    low_vote = Some(low_vote.map_or(points, |low| low.min(points)));
+                    high_vote = Some(high_vote.map_or(points, |high| high.max(points)));
+                }
+                None => votes_excluded += n,
+            }
+        }
+
+        let voters = |vote: Option<f64>| -> Vec<String> {
+            state
+                .ordered_users()
+                .into_iter()
+                .filter(|user| {
+                    vote.is_some()
+                        && revealed_points(&user.vote_status).and_then(parse_points) == vote
+                })
+                .map(|user| user.name.clone().unwrap_or_default())
+                .collect()
+        };
+
+        Some(Self {
+            mean_vote: if count > 0 {
+                Some(sum / count as f64)
+            } else {
+                None
+            },
+            low_vote,
+            high_vote,
+            low_voters: voters(low_vote),
+            high_voters: voters(high_vote),
+            votes_excluded,
+        })
+    }
+}
+
+/// Points of a user if they are visible.
+fn revealed_points(vote_status: &VoteStatus) -> Option<&str> {
+    match vote_status {
+        VoteStatus::Revealed(points) => Some(points),
+        _ => None,
+    }
+}
+
+fn parse_points(points: &str) -> Option<f64> {
+    points
+        .parse()
+        .ok()
+        .filter(|points: &f64| points.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planc_client::{SessionSettings, UserState};
+
+    fn user(name: &str, vote_status: VoteStatus) -> UserState {
+        UserState {
+            id: name.to_string(),
+            name: Some(name.to_string()),
+            vote_status,
+            ..UserState::default()
+        }
+    }
+
+    #[test]
+    fn statistics_test() {
+        let revealed = |points: &str| VoteStatus::Revealed(points.to_string());
+        let mut state = SessionState {
+            users: vec![
+                user("alice", revealed("3")),
+                user("bob", revealed("8")),
+                user("carol", revealed("?")),
+                user("dave", revealed("1")),
+            ]
+            .into_iter()
+            .map(|user| (user.id.clone(), user))
+            .collect(),
+            admin: None,
+            settings: SessionSettings::default(),
+            revealed: true,
+            distribution: None,
+        };
+        assert_eq!(
+            Statistics::from_state(&state),
+            Some(Statistics {
+                mean_vote: Some(4.0),
+                low_vote: Some(1.0),
+                high_vote: Some(8.0),
+                low_voters: vec!["dave".to_string()],
+                high_voters: vec!["bob".to_string()],
+                votes_excluded: 1,
+            })
+        );
+
+        // Anonymous mode only has the distribution.
+        for user in state.users.values_mut() {
+            user.vote_status = VoteStatus::Voted;
+        }
+        state.distribution = Some(
+            vec![("2".to_string(), 2), ("5".to_string(), 1)]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(
+            Statistics::from_state(&state),
+            Some(Statistics {
+                mean_vote: Some(3.0),
+                low_vote: Some(2.0),
+                high_vote: Some(5.0),
+                low_voters: Vec::new(),
+                high_voters: Vec::new(),
+                votes_excluded: 0,
+            })
+        );
+
+        state.revealed = false;
+        assert_eq!(Statistics::from_state(&state), None);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::app::App;
+use crate::statistics::Statistics;
+use planc_client::{Presence, SessionState, VoteStatus};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+
+/// Draw the whole application.
+pub fn render(frame: &mut Frame, app: &App) {
+    let [header, users, cards, statistics, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(3),
+        Constraint::Length(5),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(render_header(app), header);
+    match &app.state {
+        Some(state) => {
+            frame.render_widget(render_users(app, state), users);
+            frame.render_widget(render_statistics(state), statistics);
+        }
+        None => frame.render_widget(Paragraph::new("Waiting for session state..."), users),
+    }
+    frame.render_widget(render_cards(app), cards);
+    frame.render_widget(render_footer(app), footer);
+}
+
+fn render_header(app: &App) -> Paragraph<'static> {
+    let mut spans = vec![Span::from(format!("planc · session {}", app.session_id)).bold()];
+    if let Some(settings) = app.state.as_ref().map(|state| &state.settings) {
+        if settings.anonymous_voting {
+            spans.push(Span::from(" · anonymous"));
+        }
+        if settings.exclude_idle_users {
+            spans.push(Span::from(" · not waiting for idle users"));
+        }
+    }
+    Paragraph::new(Line::from(spans))
+}
+
+fn render_users(app: &App, state: &SessionState) -> Table<'static> {
+    let rows = state.ordered_users().into_iter().map(|user| {
+        let mut name = user.name.clone().unwrap_or_else(|| "-".to_string());
+        if state.admin.as_deref() == Some(user.id.as_str()) {
+            name.push_str(" 👑");
+        }
+        match user.presence {
+            Presence::Active => {}
+            Presence::Idle => name.push_str(" 💤"),
+            Presence::Disconnected => name.push_str(" 🔌"),
+        }
+        let points = if user.is_spectator {
+            "spectator".to_string()
+        } else {
+            match &user.vote_status {
+                VoteStatus::NotVoted => "…".to_string(),
+                VoteStatus::Voted => "✓".to_string(),
+                VoteStatus::Revealed(points) => points.clone(),
+            }
+        };
+        let row = Row::new(vec![name, points]);
+        if user.id == app.user_id {
+            row.style(Style::default().add_modifier(Modifier::BOLD))
+        } else {
+            row
+        }
+    });
+    Table::new(rows, [Constraint::Min(20), Constraint::Length(10)])
+        .header(Row::new(vec!["User", "Points"]).underlined())
+        .block(Block::bordered().title("Users"))
+}
+
+fn render_cards(app: &App) -> Paragraph<'static> {
+    let can_vote = app.can_vote();
+    let spans = app.cards.iter().enumerate().flat_map(|(index, card)| {
+        let style = if !can_vote {
+            Style::default().fg(Color::DarkGray)
+        } else if index == app.selected {
+            Style::default().fg(Color::Black).bg(Color::Cyan)
+        } else {
+            Style::default()
+        };
+        vec![Span::styled(format!(" {} ", card), style), Span::from(" ")]
+    });
+    Paragraph::new(Line::from(spans.collect::<Vec<_>>())).block(Block::bordered().title("Cards"))
+}
+
+fn render_statistics(state: &SessionState) -> Paragraph<'static> {
+    let block = Block::bordered().title("Statistics");
+    let statistics = match Statistics::from_state(state) {
+        Some(statistics) => statistics,
+        None => return Paragraph::new("Votes are revealed once everyone has voted.").block(block),
+    };
+    let vote = |vote: Option<f64>| vote.map_or("?".to_string(), |vote| format!("{:.0}", vote));
+    let voters = |voters: &[String]| {
+        if voters.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", voters.join(", "))
+        }
+    };
+    let mut mean = format!("Mean Vote  {}", vote(statistics.mean_vote));
+    if statistics.votes_excluded > 0 {
+        mean.push_str(&format!(" ({} votes excluded)", statistics.votes_excluded));
+    }
+    let lines = vec![
+        Line::from(mean),
+        Line::from(format!(
+            "Low Vote   {}{}",
+            vote(statistics.low_vote),
+            voters(&statistics.low_voters)
+        )),
+        Line::from(format!(
+            "High Vote  {}{}",
+            vote(statistics.high_vote),
+            voters(&statistics.high_voters)
+        )),
+    ];
+    Paragraph::new(lines).block(block)
+}
+
+fn render_footer(app: &App) -> Paragraph<'static> {
+    if let Some(err) = &app.error {
+        return Paragraph::new(format!("Error: {}", err)).red();
+    }
+    let mut help = "←/→ select · enter vote · s spectator".to_string();
+    if app.can_claim() {
+        help.push_str(" · c claim session");
+    }
+    if app.is_admin() {
+        help.push_str(" · r reset · a anonymous · i idle users");
+    }
+    help.push_str(" · q quit");
+    Paragraph::new(help).dark_gray()
+}
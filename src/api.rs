@@ -1,49 +1,130 @@
-use super::*;
+use crate::codec::Codec;
+use crate::config::HttpConfig;
+use crate::sse::{self, SseClients};
+use crate::websocket::WebSocketTransport;
+use crate::{full_body, Body, Request, Response};
+use anyhow::Result;
+use futures::FutureExt;
 use hyper::StatusCode;
+use planc_core::{Connection, ServiceContext};
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
 use tokio_tungstenite::{tungstenite, WebSocketStream};
 
-pub async fn route_request(req: Request, ctx: Arc<ServiceContext>) -> Result<Response> {
-    // Parse path '/api/<session_id>'
-    let path = req.uri().path();
-    assert!(path.starts_with("/api"));
-    let mut components = path[1..].split('/').skip(1);
-    let session_id = match components.next() {
-        Some(session_id) => session_id.to_string(),
-        None => {
-            return Ok(hyper::Response::builder()
-                .status(StatusCode::FORBIDDEN)
-                .body(Full::default())?);
-        }
+/// Endpoints of the api.
+#[derive(Debug, PartialEq, Eq)]
+enum Endpoint {
+    /// '/api/<session_id>'
+    WebSocket(String),
+    /// '/api/<session_id>/events'
+    Events(String),
+    /// '/api/<session_id>/messages'
+    Messages(String),
+}
+
+pub async fn route_request(
+    req: Request,
+    ctx: Arc<ServiceContext>,
+    http: Arc<HttpConfig>,
+    sse_clients: Arc<SseClients>,
+    peer_addr: IpAddr,
+    client_addr: IpAddr,
+) -> Result<Response> {
+    let endpoint = match parse_endpoint(req.uri().path()) {
+        Some(endpoint) => endpoint,
+        None => return error_response(StatusCode::NOT_FOUND, "Not found"),
     };
-    if components.next().is_some() {
-        return Ok(hyper::Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Full::default())?);
+
+    let trust_forwarded = http.is_trusted_proxy(peer_addr);
+    if !is_origin_allowed(&req, &http.allowed_origins, trust_forwarded) {
+        ::tracing::warn!(%client_addr, "origin_denied");
+        return error_response(StatusCode::FORBIDDEN, "Origin not allowed");
     }
 
-    let response = tungstenite::handshake::server::create_response_with_body(&req, Full::default)?;
+    match endpoint {
+        Endpoint::WebSocket(session_id) => {
+            if let Err((status, message)) = validate_handshake(&req) {
+                return error_response(status, message);
+            }
+            if let Err(err) = ctx.check_connection_rate(client_addr) {
+                ::tracing::warn!(%client_addr, "connection_rate_limited");
+                return error_response(StatusCode::TOO_MANY_REQUESTS, &err.to_string());
+            }
+            upgrade_websocket(req, ctx, http, client_addr, session_id)
+        }
+        Endpoint::Events(session_id) if req.method() == hyper::Method::GET => {
+            if let Err(err) = ctx.check_connection_rate(client_addr) {
+                ::tracing::warn!(%client_addr, "connection_rate_limited");
+                return error_response(StatusCode::TOO_MANY_REQUESTS, &err.to_string());
+            }
+            sse::events(ctx, sse_clients, client_addr, session_id)
+        }
+        Endpoint::Messages(session_id) if req.method() == hyper::Method::POST => {
+            sse::post_message(req, &sse_clients, http.max_message_size, session_id).await
+        }
+        Endpoint::Events(_) | Endpoint::Messages(_) => {
+            error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+        }
+    }
+}
+
+fn upgrade_websocket(
+    req: Request,
+    ctx: Arc<ServiceContext>,
+    http: Arc<HttpConfig>,
+    client_addr: IpAddr,
+    session_id: String,
+) -> Result<Response> {
+    let mut response = if is_extended_connect(&req) {
+        // Over HTTP/2 the handshake is accepted with a plain 200 response.
+        hyper::Response::new(Body::default())
+    } else {
+        match tungstenite::handshake::server::create_response_with_body(&req, Body::default) {
+            Ok(response) => response,
+            Err(err) => {
+                return error_response(StatusCode::BAD_REQUEST, &err.to_string());
+            }
+        }
+    };
+
+    // Negotiate the message encoding. Clients that do not request a subprotocol use JSON.
+    let requested_protocols = req
+        .headers()
+        .get_all("sec-websocket-protocol")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    let codec = match Codec::negotiate(requested_protocols) {
+        Some(codec) => {
+            response.headers_mut().insert(
+                "sec-websocket-protocol",
+                hyper::header::HeaderValue::from_static(codec.protocol()),
+            );
+            codec
+        }
+        None => Codec::Json,
+    };
     tokio::spawn(
         hyper::upgrade::on(req)
-            .then(|upgraded| async move {
+            .then(move |upgraded| async move {
                 let upgraded = hyper_util::rt::TokioIo::new(upgraded?);
+                let websocket_config = WebSocketConfig::default()
+                    .max_frame_size(Some(http.max_frame_size))
+                    .max_message_size(Some(http.max_message_size));
                 let websocket = WebSocketStream::from_raw_socket(
                     upgraded,
                     tungstenite::protocol::Role::Server,
-                    None,
+                    Some(websocket_config),
                 )
                 .await;
-                let mut connection = Connection::new(websocket);
-                match ctx.get_session(&session_id) {
-                    Ok(session) => session.join(connection).await,
-                    Err(err) => {
-                        connection
-                            .send(&ServerMessage::Error(format!(
-                                "Error joining session: {}",
-                                err
-                            )))
-                            .await
-                    }
-                }
+                let connection = Connection::new(WebSocketTransport::new(
+                    websocket,
+                    codec,
+                    http.ping_interval,
+                    http.pong_timeout,
+                ));
+                ctx.join_session(client_addr, &session_id, connection).await
             })
             .map(|result| {
                 result.unwrap_or_else(|err| {
@@ -53,3 +134,277 @@ pub async fn route_request(req: Request, ctx: Arc<ServiceContext>) -> Result<Res
     );
     Ok(response)
 }
+
+/// Parse the path of an api request.
+fn parse_endpoint(path: &str) -> Option<Endpoint> {
+    assert!(path.starts_with("/api"));
+    let mut components = path[1..].split('/').skip(1);
+    let session_id = match components.next() {
+        Some(session_id) if !session_id.is_empty() => session_id.to_string(),
+        _ => return None,
+    };
+    let endpoint = match components.next() {
+        None => Endpoint::WebSocket(session_id),
+        Some("events") => Endpoint::Events(session_id),
+        Some("messages") => Endpoint::Messages(session_id),
+        Some(_) => return None,
+    };
+    if components.next().is_some() {
+        return None;
+    }
+    Some(endpoint)
+}
+
+/// Validate a websocket handshake.
+fn validate_handshake<B>(
+    req: &hyper::Request<B>,
+) -> std::result::Result<(), (StatusCode, &'static str)> {
+    // Only websocket upgrades are supported on this path.
+    let header = |name: &str| {
+        req.headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+    };
+    if is_extended_connect(req) {
+        // Over HTTP/2 there are no upgrade headers and no key.
+        if header("sec-websocket-version") != ["13"] {
+            return Err((StatusCode::BAD_REQUEST, "Unsupported websocket version"));
+        }
+        return Ok(());
+    }
+    let is_upgrade = header("connection")
+        .iter()
+        .any(|value| value.eq_ignore_ascii_case("upgrade"));
+    let is_websocket = header("upgrade")
+        .iter()
+        .any(|value| value.eq_ignore_ascii_case("websocket"));
+    if !is_upgrade || !is_websocket {
+        return Err((StatusCode::UPGRADE_REQUIRED, "Websocket upgrade required"));
+    }
+
+    // Validate the rest of the handshake.
+    if req.method() != hyper::Method::GET {
+        return Err((StatusCode::BAD_REQUEST, "Websocket handshake must use GET"));
+    }
+    if header("sec-websocket-version") != ["13"] {
+        return Err((StatusCode::BAD_REQUEST, "Unsupported websocket version"));
+    }
+    if header("sec-websocket-key").len() != 1 {
+        return Err((StatusCode::BAD_REQUEST, "Missing websocket key"));
+    }
+    Ok(())
+}
+
+/// Check if a request is a websocket handshake over HTTP/2 (RFC 8441).
+fn is_extended_connect<B>(req: &hyper::Request<B>) -> bool {
+    req.method() == hyper::Method::CONNECT
+        && req
+            .extensions()
+            .get::<hyper::ext::Protocol>()
+            .is_some_and(|protocol| protocol.as_str().eq_ignore_ascii_case("websocket"))
+}
+
+/// Create a response with a JSON error body.
+pub fn error_response(status: StatusCode, message: &str) -> Result<Response> {
+    let mut builder = hyper::Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json");
+    if status == StatusCode::UPGRADE_REQUIRED {
+        builder = builder
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket");
+    }
+    let body = serde_json::to_vec(&serde_json::json!({ "error": message }))?;
+    Ok(builder.body(full_body(body))?)
+}
+
+/// Check the origin of a request to prevent cross-site websocket hijacking and request forgery.
+///
+/// Requests without an origin do not come from browsers and are always allowed. Without a list of
+/// allowed origins the origin has to match the host the request was sent to. Behind a trusted proxy
+/// that is the host from `X-Forwarded-Host` or `Forwarded`, since the proxy may rewrite `Host`.
+fn is_origin_allowed<B>(
+    req: &hyper::Request<B>,
+    allowed_origins: &[String],
+    trust_forwarded: bool,
+) -> bool {
+    let headers = req.headers();
+    let origin = match headers.get("origin").map(|value| value.to_str()) {
+        Some(Ok(origin)) => origin.trim_end_matches('/'),
+        Some(Err(_)) => return false,
+        None => return true,
+    };
+    if !allowed_origins.is_empty() {
+        return allowed_origins
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin));
+    }
+    let origin_host = origin.split_once("://").map(|(_, host)| host);
+    // HTTP/2 requests carry the host in the `:authority` pseudo header instead.
+    let host = trust_forwarded
+        .then(|| forwarded_host(headers))
+        .flatten()
+        .or_else(|| headers.get("host").and_then(|value| value.to_str().ok()))
+        .or_else(|| req.uri().authority().map(|authority| authority.as_str()));
+    match (origin_host, host) {
+        (Some(origin_host), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+/// Original host of a request forwarded by a proxy, taken from the first entry of `Forwarded` or
+/// `X-Forwarded-Host`.
+fn forwarded_host(headers: &hyper::HeaderMap) -> Option<&str> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &hyper::header::HeaderValue| value.to_str().ok())
+    };
+    let forwarded = header("forwarded").and_then(|forwarded| {
+        let first = forwarded.split(',').next()?;
+        first.split(';').find_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            name.eq_ignore_ascii_case("host")
+                .then(|| value.trim_matches('"'))
+        })
+    });
+    forwarded
+        .or_else(|| header("x-forwarded-host").and_then(|hosts| hosts.split(',').next()))
+        .map(str::trim)
+        .filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake(path: &str) -> hyper::http::request::Builder {
+        hyper::Request::builder()
+            .uri(path)
+            .header("Host", "planc.example.com")
+            .header("Connection", "keep-alive, Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+    }
+
+    fn status<B>(req: &hyper::Request<B>) -> StatusCode {
+        validate_handshake(req)
+            .err()
+            .map_or(StatusCode::OK, |(status, _)| status)
+    }
+
+    #[test]
+    fn parse_endpoint_test() {
+        let session_id = || "abcd".to_string();
+        assert_eq!(
+            parse_endpoint("/api/abcd"),
+            Some(Endpoint::WebSocket(session_id()))
+        );
+        assert_eq!(
+            parse_endpoint("/api/abcd/events"),
+            Some(Endpoint::Events(session_id()))
+        );
+        assert_eq!(
+            parse_endpoint("/api/abcd/messages"),
+            Some(Endpoint::Messages(session_id()))
+        );
+
+        // Bad paths.
+        assert_eq!(parse_endpoint("/api"), None);
+        assert_eq!(parse_endpoint("/api/"), None);
+        assert_eq!(parse_endpoint("/api/abcd/efgh"), None);
+        assert_eq!(parse_endpoint("/api/abcd/events/efgh"), None);
+    }
+
+    #[test]
+    fn validate_handshake_test() {
+        let req = handshake("/api/abcd").body(()).unwrap();
+        assert_eq!(status(&req), StatusCode::OK);
+
+        // Plain requests.
+        let req = hyper::Request::builder().uri("/api/abcd").body(()).unwrap();
+        assert_eq!(status(&req), StatusCode::UPGRADE_REQUIRED);
+
+        // Malformed handshakes.
+        let req = handshake("/api/abcd").method("POST").body(()).unwrap();
+        assert_eq!(status(&req), StatusCode::BAD_REQUEST);
+        let mut req = handshake("/api/abcd").body(()).unwrap();
+        req.headers_mut().remove("sec-websocket-key");
+        assert_eq!(status(&req), StatusCode::BAD_REQUEST);
+        let mut req = handshake("/api/abcd").body(()).unwrap();
+        req.headers_mut()
+            .insert("sec-websocket-version", "8".parse().unwrap());
+        assert_eq!(status(&req), StatusCode::BAD_REQUEST);
+
+        // Extended CONNECT over HTTP/2.
+        let mut req = hyper::Request::builder()
+            .method("CONNECT")
+            .uri("https://planc.example.com/api/abcd")
+            .header("Sec-WebSocket-Version", "13")
+            .body(())
+            .unwrap();
+        assert_eq!(status(&req), StatusCode::UPGRADE_REQUIRED);
+        req.extensions_mut()
+            .insert(hyper::ext::Protocol::from_static("websocket"));
+        assert_eq!(status(&req), StatusCode::OK);
+    }
+
+    #[test]
+    fn error_response_test() {
+        let response = error_response(StatusCode::UPGRADE_REQUIRED, "Upgrade").unwrap();
+        assert_eq!(response.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(response.headers()["upgrade"], "websocket");
+        assert_eq!(response.headers()["content-type"], "application/json");
+    }
+
+    #[test]
+    fn is_origin_allowed_test() {
+        let req = handshake("/api/abcd").body(()).unwrap();
+        assert!(is_origin_allowed(&req, &[], false));
+
+        let req = handshake("/api/abcd")
+            .header("Origin", "https://planc.example.com")
+            .body(())
+            .unwrap();
+        assert!(is_origin_allowed(&req, &[], false));
+
+        let req = handshake("/api/abcd")
+            .header("Origin", "https://evil.example.com")
+            .body(())
+            .unwrap();
+        assert!(!is_origin_allowed(&req, &[], false));
+        assert!(is_origin_allowed(
+            &req,
+            &["https://evil.example.com".to_string()],
+            false
+        ));
+
+        // A proxy on a unix socket sends `Host: localhost` and the original host separately, which
+        // is only used if the proxy is trusted.
+        let proxied = |name, value| {
+            let mut req = handshake("/api/abcd")
+                .header("Origin", "https://planc.example.com")
+                .header(name, value)
+                .body(())
+                .unwrap();
+            req.headers_mut()
+                .insert("host", "localhost".parse().unwrap());
+            req
+        };
+        let req = proxied("X-Forwarded-Host", "planc.example.com");
+        assert!(is_origin_allowed(&req, &[], true));
+        assert!(!is_origin_allowed(&req, &[], false));
+        let req = proxied(
+            "Forwarded",
+            "for=192.0.2.1;host=planc.example.com, for=10.0.0.1",
+        );
+        assert!(is_origin_allowed(&req, &[], true));
+        let req = proxied("X-Forwarded-Host", "evil.example.com");
+        assert!(!is_origin_allowed(&req, &[], true));
+    }
+}
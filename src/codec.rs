@@ -0,0 +1,95 @@
+use anyhow::Result;
+use planc_core::PlancError;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+use tokio_tungstenite::tungstenite::Utf8Bytes;
+
+/// Encoding of messages on a connection.
+///
+/// The codec is negotiated with the `Sec-WebSocket-Protocol` header. Clients that do not request a
+/// subprotocol use JSON.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    /// JSON in text frames.
+    #[default]
+    Json,
+    /// MessagePack in binary frames.
+    MessagePack,
+}
+
+impl Codec {
+    /// Subprotocol name of this codec.
+    pub fn protocol(self) -> &'static str {
+        match self {
+            Codec::Json => "planc.json.v1",
+            Codec::MessagePack => "planc.msgpack.v1",
+        }
+    }
+
+    /// Choose the first supported codec from the subprotocols requested by a client.
+    pub fn negotiate<'a>(protocols: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        protocols
+            .into_iter()
+            .map(str::trim)
+            .find_map(|protocol| match protocol {
+                "planc.json.v1" => Some(Codec::Json),
+                "planc.msgpack.v1" => Some(Codec::MessagePack),
+                _ => None,
+            })
+    }
+
+    /// Whether a data frame has the frame type used by this codec.
+    pub fn accepts(self, msg: &WebSocketMessage) -> bool {
+        matches!(
+            (self, msg),
+            (Codec::Json, WebSocketMessage::Text(_))
+                | (Codec::MessagePack, WebSocketMessage::Binary(_))
+        )
+    }
+
+    pub fn encode<T: Serialize>(self, msg: &T) -> Result<WebSocketMessage> {
+        Ok(match self {
+            Codec::Json => WebSocketMessage::Text(Utf8Bytes::from(serde_json::to_string(msg)?)),
+            // Structs are encoded as maps so the field names match the JSON encoding.
+            Codec::MessagePack => WebSocketMessage::Binary(rmp_serde::to_vec_named(msg)?.into()),
+        })
+    }
+
+    pub fn decode<T>(self, msg: &WebSocketMessage) -> Result<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match (self, msg) {
+            (Codec::Json, WebSocketMessage::Text(text)) => Ok(serde_json::from_str(text)?),
+            (Codec::MessagePack, WebSocketMessage::Binary(data)) => {
+                Ok(rmp_serde::from_slice(data)?)
+            }
+            _ => Err(PlancError::InvalidMessage.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planc_core::ClientMessage;
+
+    #[test]
+    fn codec_test() {
+        assert_eq!(Codec::negotiate([]), None);
+        assert_eq!(
+            Codec::negotiate(["chat", " planc.msgpack.v1", "planc.json.v1"]),
+            Some(Codec::MessagePack)
+        );
+
+        let msg = serde_json::json!({ "tag": "SetPoints", "content": "5" });
+        for codec in [Codec::Json, Codec::MessagePack] {
+            let encoded = codec.encode(&msg).unwrap();
+            assert!(codec.accepts(&encoded));
+            match codec.decode(&encoded).unwrap() {
+                ClientMessage::SetPoints(points) => assert_eq!(points, "5"),
+                msg => panic!("Unexpected message: {:?}", msg),
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use ipnet::IpNet;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// HTTP and websocket settings of a server. Session limits are configured with
+/// [`ServiceContextConfig`](planc_core::ServiceContextConfig).
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    /// Time between a client's response to a websocket ping and the next ping.
+    pub ping_interval: Duration,
+    /// Time to wait for a response to a ping before the connection is considered dead.
+    pub pong_timeout: Duration,
+    /// Maximum size of a single websocket frame in bytes.
+    pub max_frame_size: usize,
+    /// Maximum size of a websocket message or posted message in bytes.
+    pub max_message_size: usize,
+    /// Origins allowed to open websocket connections. If empty only same-origin requests are
+    /// allowed.
+    pub allowed_origins: Vec<String>,
+    /// Networks of reverse proxies whose x-forwarded-for headers are trusted.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(15),
+            pong_timeout: Duration::from_secs(15),
+            max_frame_size: 16 << 10,
+            max_message_size: 16 << 10,
+            allowed_origins: Vec::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Determine the address of the client behind a request.
+    ///
+    /// The x-forwarded-for header is only used if the request comes from a trusted proxy. It is
+    /// read from right to left, skipping trusted proxies, because only the entries appended by our
+    /// own proxies can be trusted.
+    pub fn client_addr(&self, peer_addr: IpAddr, forwarded_for: &str) -> IpAddr {
+        let is_trusted = |addr: &IpAddr| self.is_trusted_proxy(*addr);
+        if !is_trusted(&peer_addr) {
+            return peer_addr;
+        }
+        let mut client_addr = peer_addr;
+        for entry in forwarded_for.rsplit(',') {
+            match entry.trim().parse() {
+                Ok(addr) => {
+                    client_addr = addr;
+                    if !is_trusted(&addr) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        client_addr
+    }
+
+    /// Check whether forwarding headers of a peer can be trusted.
+    pub fn is_trusted_proxy(&self, peer_addr: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|net| net.contains(&peer_addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_addr_test() {
+        let config = HttpConfig {
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+            ..HttpConfig::default()
+        };
+        let proxy = IpAddr::from([10, 0, 0, 1]);
+        let client = IpAddr::from([192, 0, 2, 1]);
+
+        // Headers from untrusted peers are ignored.
+        assert_eq!(config.client_addr(client, "198.51.100.1"), client);
+        // The last untrusted entry is the client, entries added by the client itself are ignored.
+        assert_eq!(config.client_addr(proxy, "192.0.2.1"), client);
+        assert_eq!(
+            config.client_addr(proxy, "198.51.100.1, 192.0.2.1, 10.0.0.2"),
+            client
+        );
+        // Requests from the proxy itself.
+        assert_eq!(config.client_addr(proxy, ""), proxy);
+    }
+}
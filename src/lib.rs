@@ -0,0 +1,42 @@
+//! HTTP server of planc.
+//!
+//! [`PlancServer`] serves the api under `/api` and the frontend for all other paths. It can run its
+//! own accept loop on TCP or unix sockets, or be mounted in an existing hyper based service.
+
+mod api;
+mod codec;
+mod config;
+mod listener;
+mod security;
+mod server;
+mod sse;
+mod web;
+mod websocket;
+
+pub use self::codec::Codec;
+pub use self::config::HttpConfig;
+pub use self::listener::{BindError, Listener, SystemdSockets};
+pub use self::security::SecurityHeaders;
+pub use self::server::{AuthHook, PlancServer, PlancServerBuilder, PlancService};
+pub use self::sse::SseTransport;
+pub use self::websocket::WebSocketTransport;
+pub use planc_core;
+
+use anyhow::Error;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+
+type Request = hyper::Request<Body>;
+
+/// Body of requests and responses.
+pub type Body = UnsyncBoxBody<Bytes, Error>;
+
+pub type Response = hyper::Response<Body>;
+
+/// Create a response body from a complete buffer.
+fn full_body(data: impl Into<Bytes>) -> Body {
+    Full::new(data.into())
+        .map_err(|never| match never {})
+        .boxed_unsync()
+}
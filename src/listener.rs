@@ -0,0 +1,246 @@
+use anyhow::Result;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::path::Path;
+use std::{fmt, io};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// First file descriptor passed by systemd socket activation.
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Sockets passed by systemd socket activation, see [`Listener::take_systemd_sockets`].
+#[derive(Debug, Default)]
+pub struct SystemdSockets {
+    #[cfg(unix)]
+    sockets: Vec<socket2::Socket>,
+}
+
+/// Socket accepting connections for [`PlancServer::serve`].
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    /// Connections over a unix socket have no peer address. Each one is treated as coming from
+    /// its own address in `127.0.0.0/8`, like a reverse proxy on the same host, so rate limits
+    /// apply per connection.
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// Error binding a listener, e.g. because the address is in use or cannot be resolved.
+#[derive(Debug)]
+pub struct BindError {
+    /// The address as given, e.g. `localhost:8080`.
+    pub address: String,
+    pub source: io::Error,
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to bind {}: {}", self.address, self.source)
+    }
+}
+
+impl std::error::Error for BindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl Listener {
+    /// Bind TCP sockets on all addresses of `hosts`, IP addresses or hostnames like `localhost`.
+    ///
+    /// IPv6 sockets keep the dual-stack default of the OS, so `::` alone also accepts IPv4
+    /// connections on most systems. Only if `0.0.0.0` is bound as well, `::` is restricted to IPv6
+    /// so both can share the port.
+    pub async fn bind(
+        hosts: &[impl AsRef<str>],
+        port: u16,
+    ) -> std::result::Result<Vec<Self>, BindError> {
+        let mut addrs = Vec::new();
+        for host in hosts {
+            let host = host.as_ref().trim_start_matches('[').trim_end_matches(']');
+            let bind_error = |source| BindError {
+                address: if host.contains(':') {
+                    format!("[{}]:{}", host, port)
+                } else {
+                    format!("{}:{}", host, port)
+                },
+                source,
+            };
+            addrs.extend(
+                tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(bind_error)?,
+            );
+        }
+        addrs.sort_unstable();
+        addrs.dedup();
+        let ipv4_wildcard = addrs
+            .iter()
+            .any(|addr| addr.ip() == IpAddr::from(Ipv4Addr::UNSPECIFIED));
+        addrs
+            .into_iter()
+            .map(|addr| {
+                let only_v6 = ipv4_wildcard && addr.ip() == IpAddr::from(Ipv6Addr::UNSPECIFIED);
+                let listener = bind_tcp(addr, only_v6).map_err(|source| BindError {
+                    address: addr.to_string(),
+                    source,
+                })?;
+                ::tracing::info!(local_addr = %addr, "listening");
+                Ok(Listener::Tcp(listener))
+            })
+            .collect()
+    }
+
+    /// Bind a unix socket at `path`, replacing the socket file of a previous run.
+    #[cfg(unix)]
+    pub fn bind_unix(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let is_socket =
+            std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket());
+        if is_socket {
+            std::fs::remove_file(path)?;
+        }
+        Ok(Listener::Unix(UnixListener::bind(path)?))
+    }
+
+    /// Take the sockets passed by systemd socket activation (`LISTEN_FDS`). Returns no sockets if
+    /// the process was not socket activated or is not running on unix.
+    ///
+    /// The environment variables are removed so the sockets cannot be taken twice. Modifying the
+    /// environment is only sound while the process is single-threaded, so this must be called
+    /// before the async runtime is started. Use [`Listener::from_systemd`] to listen on the
+    /// sockets.
+    pub fn take_systemd_sockets() -> Result<SystemdSockets> {
+        #[cfg(unix)]
+        {
+            use anyhow::Context;
+            use std::os::unix::io::FromRawFd;
+
+            let listen_pid = std::env::var("LISTEN_PID").ok();
+            if listen_pid != Some(std::process::id().to_string()) {
+                return Ok(SystemdSockets::default());
+            }
+            let listen_fds: i32 = std::env::var("LISTEN_FDS")
+                .context("LISTEN_FDS not set")?
+                .parse()
+                .context("Invalid LISTEN_FDS")?;
+            for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+                std::env::remove_var(name);
+            }
+            let sockets = (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + listen_fds)
+                // Safety: systemd passes the descriptors to this process, and they are only taken
+                // once because the environment variables were removed.
+                .map(|fd| unsafe { socket2::Socket::from_raw_fd(fd) })
+                .collect();
+            Ok(SystemdSockets { sockets })
+        }
+        #[cfg(not(unix))]
+        Ok(SystemdSockets::default())
+    }
+
+    /// Listen on the sockets passed by systemd. Must be called within the tokio runtime.
+    pub fn from_systemd(sockets: SystemdSockets) -> Result<Vec<Self>> {
+        #[cfg(unix)]
+        {
+            use anyhow::Context;
+            use std::os::unix::io::AsRawFd;
+
+            sockets
+                .sockets
+                .into_iter()
+                .map(|socket| {
+                    let fd = socket.as_raw_fd();
+                    Self::from_socket(socket).with_context(|| format!("Invalid socket {}", fd))
+                })
+                .collect()
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = sockets;
+            Ok(Vec::new())
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_socket(socket: socket2::Socket) -> Result<Self> {
+        if socket.r#type()? != socket2::Type::STREAM {
+            anyhow::bail!("Not a stream socket");
+        }
+        socket.set_nonblocking(true)?;
+        if socket.local_addr()?.is_unix() {
+            let listener =
+                std::os::unix::net::UnixListener::from(std::os::fd::OwnedFd::from(socket));
+            Ok(Listener::Unix(UnixListener::from_std(listener)?))
+        } else {
+            Ok(Listener::Tcp(TcpListener::from_std(socket.into())?))
+        }
+    }
+}
+
+fn bind_tcp(addr: SocketAddr, only_v6: bool) -> io::Result<TcpListener> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        None,
+    )?;
+    if only_v6 {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Listener::Tcp(listener)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for Listener {
+    fn from(listener: UnixListener) -> Self {
+        Listener::Unix(listener)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bind_test() {
+        let listeners = Listener::bind(&["127.0.0.1"], 0).await.unwrap();
+        let port = match &listeners[..] {
+            [Listener::Tcp(listener)] => listener.local_addr().unwrap().port(),
+            _ => panic!("{:?}", listeners),
+        };
+
+        let err = Listener::bind(&["127.0.0.1"], port).await.unwrap_err();
+        assert_eq!(err.address, format!("127.0.0.1:{}", port));
+        assert_eq!(err.source.kind(), io::ErrorKind::AddrInUse);
+        assert!(!Listener::bind(&["localhost"], 0).await.unwrap().is_empty());
+
+        // Both wildcards can share a port.
+        let listeners = Listener::bind(&["0.0.0.0"], 0).await.unwrap();
+        let port = match &listeners[..] {
+            [Listener::Tcp(listener)] => listener.local_addr().unwrap().port(),
+            _ => panic!("{:?}", listeners),
+        };
+        drop(listeners);
+        match Listener::bind(&["0.0.0.0", "::"], port).await {
+            Ok(listeners) => assert_eq!(listeners.len(), 2),
+            // Hosts without IPv6.
+            Err(err) => assert_ne!(err.source.kind(), io::ErrorKind::AddrInUse),
+        }
+    }
+}
@@ -1,51 +1,93 @@
-mod api;
-mod connection;
-mod context;
-mod error;
-mod protocol;
-mod session;
-mod web;
-
-pub use self::connection::*;
-pub use self::context::*;
-pub use self::error::*;
-pub use self::protocol::*;
-pub use self::session::*;
-
-use anyhow::{Error, Result};
 use clap::Parser;
-use futures::prelude::*;
-use http_body_util::Full;
-use hyper::body::Bytes;
-use std::net::SocketAddr;
-use std::pin::Pin;
-use std::sync::Arc;
-use tokio::net::TcpListener;
+use planc_server::planc_core::{RateLimit, ServiceContextConfig};
+use planc_server::{HttpConfig, Listener, PlancServer, SecurityHeaders, SystemdSockets};
 use tracing_subscriber::prelude::*;
 
-type Request = hyper::Request<hyper::body::Incoming>;
-type Response = hyper::Response<Full<Bytes>>;
-
 /// Command line arguments
 #[derive(Parser, Debug)]
 #[clap(version, author, about)]
 struct Args {
-    /// HTTP listener address
-    #[clap(long, short = 'a')]
-    bind_address: String,
+    /// HTTP listener address, an IP address or a hostname (may be repeated)
+    #[clap(long = "bind-address", short = 'a', requires = "bind_port")]
+    bind_addresses: Vec<String>,
     /// HTTP listener port
-    #[clap(long, short = 'p')]
-    bind_port: u16,
+    #[clap(long, short = 'p', requires = "bind_addresses")]
+    bind_port: Option<u16>,
+    /// Path of a unix socket to listen on, e.g. for a reverse proxy on the same host
+    #[cfg(unix)]
+    #[clap(long)]
+    unix_socket: Option<std::path::PathBuf>,
     /// Maximum number of concurrent sessions
     #[clap(long, default_value_t = 8)]
     max_sessions: usize,
     /// Maximum number of users in a session
     #[clap(long, default_value_t = 16)]
     max_users: usize,
+    /// Seconds without messages after which a user is considered idle
+    #[clap(long, default_value_t = 300)]
+    idle_timeout: u64,
+    /// Seconds a disconnected user stays in the session before being removed
+    #[clap(long, default_value_t = 10)]
+    disconnect_grace_period: u64,
+    /// Seconds between a client's response to a websocket ping and the next ping
+    #[clap(long, default_value_t = 15, value_parser = clap::value_parser!(u64).range(1..))]
+    ping_interval: u64,
+    /// Seconds to wait for a response to a ping before dropping the connection
+    #[clap(long, default_value_t = 15, value_parser = clap::value_parser!(u64).range(1..))]
+    pong_timeout: u64,
+    /// Maximum size of a websocket frame in bytes
+    #[clap(long, default_value_t = 16 << 10)]
+    max_frame_size: usize,
+    /// Maximum size of a websocket message in bytes
+    #[clap(long, default_value_t = 16 << 10)]
+    max_message_size: usize,
+    /// Origin allowed to open websocket connections, e.g. https://planc.example.com (may be
+    /// repeated, only same-origin requests are allowed if not set)
+    #[clap(long = "allowed-origin")]
+    allowed_origins: Vec<String>,
+    /// Network of a reverse proxy whose x-forwarded-for header is trusted, e.g. 10.0.0.0/8 (may be
+    /// repeated)
+    #[clap(long = "trusted-proxy")]
+    trusted_proxies: Vec<ipnet::IpNet>,
+    /// Path prefix of all routes, e.g. /tools/planc (served at the root if not set)
+    #[clap(long, default_value = "")]
+    base_path: String,
+    /// Directory with frontend assets that take precedence over the embedded ones, read from disk
+    /// on every request without caching
+    #[clap(long)]
+    assets_dir: Option<std::path::PathBuf>,
+    /// Origin allowed to embed the frontend in a frame, e.g. https://wiki.example.com (may be
+    /// repeated, framing is denied if not set)
+    #[clap(long = "frame-ancestor")]
+    frame_ancestors: Vec<String>,
+    /// Seconds browsers should only use HTTPS for this host (Strict-Transport-Security, not sent
+    /// if not set)
+    #[clap(long)]
+    hsts_max_age: Option<u64>,
+    /// Content-Security-Policy replacing the default policy of the frontend
+    #[clap(long)]
+    content_security_policy: Option<String>,
+    /// Messages per second a single connection may send
+    #[clap(long, default_value_t = 5.0)]
+    message_rate: f64,
+    /// Messages a single connection may send in a burst
+    #[clap(long, default_value_t = 20.0)]
+    message_burst: f64,
+    /// New connections per second a single client address may open
+    #[clap(long, default_value_t = 1.0)]
+    connection_rate: f64,
+    /// New connections a single client address may open in a burst
+    #[clap(long, default_value_t = 10.0)]
+    connection_burst: f64,
+    /// New sessions per second a single client address may create
+    #[clap(long, default_value_t = 0.1)]
+    session_rate: f64,
+    /// New sessions a single client address may create in a burst
+    #[clap(long, default_value_t = 5.0)]
+    session_burst: f64,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     // Initialize tracing.
     let tracing_format = ::tracing_subscriber::fmt::layer()
         .json()
@@ -63,85 +105,81 @@ async fn main() -> anyhow::Result<()> {
     // Parse command line arguments
     let args = Args::parse();
 
-    // Create socket address from command line arguments
-    ::tracing::info!(
-        bind_address = args.bind_address,
-        bind_port = args.bind_port,
-        "binding_listener"
-    );
-    let bind_address: std::net::IpAddr = args
-        .bind_address
-        .parse()
-        .expect("Failed to parse bind address");
-    let socket_address = std::net::SocketAddr::new(bind_address, args.bind_port);
-
-    // Create service context config
-    let ctx = Arc::new(ServiceContext::new(ServiceContextConfig {
-        max_sessions: args.max_sessions,
-        max_users: args.max_users,
-    }));
+    // Take the sockets passed by systemd socket activation before the runtime starts its threads,
+    // because this modifies the environment.
+    let systemd_sockets = Listener::take_systemd_sockets()?;
 
-    // Create tcp listener.
-    let tcp_listener = TcpListener::bind(&socket_address).await?;
+    tokio::runtime::Runtime::new()?.block_on(run(args, systemd_sockets))
+}
 
-    loop {
-        match tcp_listener.accept().await {
-            Ok((tcp_stream, peer_addr)) => {
-                ::tracing::info!(peer_addr = peer_addr.to_string(), "incoming_connection");
-                let service = Service::new(Arc::clone(&ctx), peer_addr);
-                tokio::spawn(async move {
-                    let tcp_stream = hyper_util::rt::TokioIo::new(tcp_stream);
-                    let result = hyper::server::conn::http1::Builder::new()
-                        .serve_connection(tcp_stream, service)
-                        .with_upgrades()
-                        .await;
-                    if let Err(err) = result {
-                        ::tracing::warn!(?err, "connection");
-                    }
-                });
+async fn run(args: Args, systemd_sockets: SystemdSockets) -> anyhow::Result<()> {
+    // Create listeners, including sockets passed by systemd socket activation.
+    let mut listeners = Listener::from_systemd(systemd_sockets)?;
+    if !listeners.is_empty() {
+        ::tracing::info!(count = listeners.len(), "systemd_listeners");
+    }
+    if let Some(bind_port) = args.bind_port {
+        ::tracing::info!(bind_addresses = ?args.bind_addresses, bind_port, "binding_listeners");
+        match Listener::bind(&args.bind_addresses, bind_port).await {
+            Ok(tcp_listeners) => listeners.extend(tcp_listeners),
+            Err(err) => {
+                ::tracing::error!(address = err.address, error = %err.source, "bind_failed");
+                return Err(err.into());
             }
-            Err(err) => ::tracing::warn!(?err, "accept"),
         }
     }
-}
-
-struct Service {
-    ctx: Arc<ServiceContext>,
-    peer_addr: SocketAddr,
-}
-
-impl Service {
-    pub fn new(ctx: Arc<ServiceContext>, peer_addr: SocketAddr) -> Self {
-        Self { ctx, peer_addr }
+    #[cfg(unix)]
+    if let Some(unix_socket) = args.unix_socket {
+        ::tracing::info!(path = %unix_socket.display(), "binding_unix_listener");
+        listeners.push(Listener::bind_unix(&unix_socket)?);
     }
-}
-
-impl hyper::service::Service<Request> for Service {
-    type Response = Response;
-    type Error = Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response>> + Send>>;
-
-    fn call(&self, req: Request) -> Self::Future {
-        let method = req.method().as_str();
-        let path = req.uri().path();
-        let peer_addr = self.peer_addr.to_string();
-        let forwarded_for = req
-            .headers()
-            .get("x-forwarded-for")
-            .map(|value| value.to_str().unwrap_or_default())
-            .unwrap_or_default();
-        ::tracing::info!(method, path, peer_addr, forwarded_for, "incoming_request");
-        let ctx = self.ctx.clone();
-        Box::pin(async move { route_request(req, ctx).await })
+    if listeners.is_empty() {
+        anyhow::bail!("No listener, set --bind-address and --bind-port or --unix-socket");
     }
-}
-
-async fn route_request(req: Request, ctx: Arc<ServiceContext>) -> Result<Response> {
-    let path = req.uri().path();
-    assert!(path.starts_with('/'));
 
-    match path[1..].split('/').next() {
-        Some("api") => api::route_request(req, ctx).await,
-        _ => web::route_request(req).await,
+    // Create server.
+    let config = ServiceContextConfig {
+        max_sessions: args.max_sessions,
+        max_users: args.max_users,
+        idle_timeout: std::time::Duration::from_secs(args.idle_timeout),
+        disconnect_grace_period: std::time::Duration::from_secs(args.disconnect_grace_period),
+        message_rate_limit: RateLimit {
+            rate: args.message_rate,
+            burst: args.message_burst,
+        },
+        connection_rate_limit: RateLimit {
+            rate: args.connection_rate,
+            burst: args.connection_burst,
+        },
+        session_rate_limit: RateLimit {
+            rate: args.session_rate,
+            burst: args.session_burst,
+        },
+    };
+    let http_config = HttpConfig {
+        ping_interval: std::time::Duration::from_secs(args.ping_interval),
+        pong_timeout: std::time::Duration::from_secs(args.pong_timeout),
+        max_frame_size: args.max_frame_size,
+        max_message_size: args.max_message_size,
+        allowed_origins: args.allowed_origins,
+        trusted_proxies: args.trusted_proxies,
+    };
+    let security_headers = SecurityHeaders {
+        frame_ancestors: args.frame_ancestors,
+        hsts_max_age: args.hsts_max_age.map(std::time::Duration::from_secs),
+        content_security_policy: args.content_security_policy,
+    };
+    let mut builder = PlancServer::builder()
+        .config(config)
+        .http_config(http_config)
+        .security_headers(security_headers)
+        .base_path(&args.base_path);
+    if let Some(assets_dir) = args.assets_dir {
+        builder = builder.assets_dir(assets_dir);
     }
+    let server = builder.build();
+
+    futures::future::try_join_all(listeners.into_iter().map(|listener| server.serve(listener)))
+        .await?;
+    Ok(())
 }
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use std::time::Duration;
+
+/// Content security policy of the frontend, without `frame-ancestors`. Styles are injected at
+/// runtime by the component library and need `'unsafe-inline'`.
+const CONTENT_SECURITY_POLICY: &str = "default-src 'self'; connect-src 'self'; \
+     img-src 'self' data:; style-src 'self' 'unsafe-inline'; base-uri 'self'; form-action 'self'";
+
+/// Security headers added to all frontend responses.
+#[derive(Debug, Clone, Default)]
+pub struct SecurityHeaders {
+    /// Origins allowed to embed the frontend in a frame, e.g. `https://wiki.example.com`. Framing
+    /// by other origins is denied.
+    pub frame_ancestors: Vec<String>,
+    /// Max age of the Strict-Transport-Security header. Only set this if the server is reached
+    /// over HTTPS exclusively.
+    pub hsts_max_age: Option<Duration>,
+    /// Content-Security-Policy replacing the default policy. It needs to include
+    /// `frame-ancestors` itself.
+    pub content_security_policy: Option<String>,
+}
+
+impl SecurityHeaders {
+    /// Add the headers to a response.
+    pub fn apply(&self, headers: &mut HeaderMap) -> Result<()> {
+        let content_security_policy = match &self.content_security_policy {
+            Some(policy) => policy.clone(),
+            None => format!(
+                "{}; frame-ancestors {}",
+                CONTENT_SECURITY_POLICY,
+                self.frame_ancestors_source()
+            ),
+        };
+        headers.insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_str(&content_security_policy)?,
+        );
+        // X-Frame-Options cannot express a list of origins, so it is left to the policy if framing
+        // is allowed.
+        if self.frame_ancestors.is_empty() {
+            headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        }
+        headers.insert(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        );
+        // Session ids are part of the URL and must not leak to other sites.
+        headers.insert(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        );
+        if let Some(max_age) = self.hsts_max_age {
+            headers.insert(
+                header::STRICT_TRANSPORT_SECURITY,
+                HeaderValue::from_str(&format!("max-age={}", max_age.as_secs()))?,
+            );
+        }
+        Ok(())
+    }
+
+    fn frame_ancestors_source(&self) -> String {
+        if self.frame_ancestors.is_empty() {
+            "'none'".to_string()
+        } else {
+            let mut sources = vec!["'self'".to_string()];
+            sources.extend(self.frame_ancestors.iter().cloned());
+            sources.join(" ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn security_headers_test() {
+        let mut headers = HeaderMap::new();
+        SecurityHeaders::default().apply(&mut headers).unwrap();
+        let csp = headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+        assert!(csp.ends_with("; frame-ancestors 'none'"), "{}", csp);
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+        assert!(headers.get(header::STRICT_TRANSPORT_SECURITY).is_none());
+
+        let mut headers = HeaderMap::new();
+        SecurityHeaders {
+            frame_ancestors: vec!["https://wiki.example.com".to_string()],
+            hsts_max_age: Some(Duration::from_secs(86400)),
+            content_security_policy: None,
+        }
+        .apply(&mut headers)
+        .unwrap();
+        let csp = headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+        assert!(
+            csp.ends_with("; frame-ancestors 'self' https://wiki.example.com"),
+            "{}",
+            csp
+        );
+        assert!(headers.get(header::X_FRAME_OPTIONS).is_none());
+        assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], "max-age=86400");
+    }
+}
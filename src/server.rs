@@ -0,0 +1,679 @@
+use crate::config::HttpConfig;
+use crate::listener::Listener;
+use crate::security::SecurityHeaders;
+use crate::sse::SseClients;
+use crate::{api, full_body, web, Body, Request, Response};
+use anyhow::{Error, Result};
+use futures::Future;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::http::request::Parts;
+use hyper_util::rt::TokioExecutor;
+use hyper_util::server::conn::auto;
+use planc_core::{ServiceContext, ServiceContextConfig};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Hook to authorize api requests. Returning a response rejects the request with it.
+pub type AuthHook = Arc<dyn Fn(&Parts, IpAddr) -> Option<Response> + Send + Sync + 'static>;
+
+/// The planc service: the api under `/api` and the frontend for all other paths.
+///
+/// Use [`PlancServer::serve`] to run a standalone server, or [`PlancServer::service`] to mount
+/// it in an existing hyper server.
+#[derive(Clone)]
+pub struct PlancServer {
+    ctx: Arc<ServiceContext>,
+    http: Arc<HttpConfig>,
+    sse_clients: Arc<SseClients>,
+    auth: Option<AuthHook>,
+    assets: web::Assets,
+    security_headers: SecurityHeaders,
+    /// Prefix of all routes without trailing slash, empty if served at the root.
+    base_path: String,
+}
+
+impl PlancServer {
+    pub fn builder() -> PlancServerBuilder {
+        PlancServerBuilder {
+            config: ServiceContextConfig::default(),
+            http_config: HttpConfig::default(),
+            auth: None,
+            assets_dir: None,
+            security_headers: SecurityHeaders::default(),
+            base_path: String::new(),
+        }
+    }
+
+    pub fn context(&self) -> &Arc<ServiceContext> {
+        &self.ctx
+    }
+
+    /// Create a service for the requests of a connection from `peer_addr`.
+    pub fn service(&self, peer_addr: SocketAddr) -> PlancService {
+        PlancService {
+            server: self.clone(),
+            peer_addr,
+        }
+    }
+
+    /// Accept connections from a listener and serve them.
+    pub async fn serve(&self, listener: impl Into<Listener>) -> Result<()> {
+        match listener.into() {
+            Listener::Tcp(listener) => loop {
+                match listener.accept().await {
+                    Ok((stream, peer_addr)) => self.spawn_connection(stream, peer_addr),
+                    Err(err) => ::tracing::warn!(?err, "accept"),
+                }
+            },
+            #[cfg(unix)]
+            Listener::Unix(listener) => loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => self.spawn_connection(stream, unix_peer_addr()),
+                    Err(err) => ::tracing::warn!(?err, "accept"),
+                }
+            },
+        }
+    }
+
+    fn spawn_connection<S>(&self, stream: S, peer_addr: SocketAddr)
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    {
+        ::tracing::info!(peer_addr = peer_addr.to_string(), "incoming_connection");
+        let service = self.service(peer_addr);
+        tokio::spawn(async move {
+            let stream = hyper_util::rt::TokioIo::new(stream);
+            let result = connection_builder()
+                .serve_connection_with_upgrades(stream, service)
+                .await;
+            if let Err(err) = result {
+                ::tracing::warn!(?err, "connection");
+            }
+        });
+    }
+}
+
+/// Address for a connection over a unix socket. Every connection gets its own loopback address so
+/// per-client rate limits do not throttle all local clients together.
+#[cfg(unix)]
+fn unix_peer_addr() -> SocketAddr {
+    static NEXT_PEER: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(1);
+    let peer = NEXT_PEER.fetch_add(1, std::sync::atomic::Ordering::Relaxed) & 0x00ff_ffff;
+    SocketAddr::from((std::net::Ipv4Addr::from(0x7f00_0000 | peer), 0))
+}
+
+/// Create a builder for connections that negotiates HTTP/1 or HTTP/2. Websockets over HTTP/2 use
+/// the extended CONNECT protocol (RFC 8441).
+fn connection_builder() -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http2().enable_connect_protocol();
+    builder
+}
+
+/// Builder for [`PlancServer`].
+pub struct PlancServerBuilder {
+    config: ServiceContextConfig,
+    http_config: HttpConfig,
+    auth: Option<AuthHook>,
+    assets_dir: Option<PathBuf>,
+    security_headers: SecurityHeaders,
+    base_path: String,
+}
+
+impl PlancServerBuilder {
+    /// Set limits and timeouts of sessions. Defaults to [`ServiceContextConfig::default`].
+    pub fn config(mut self, config: ServiceContextConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set websocket limits, allowed origins and trusted proxies. Defaults to
+    /// [`HttpConfig::default`].
+    pub fn http_config(mut self, http_config: HttpConfig) -> Self {
+        self.http_config = http_config;
+        self
+    }
+
+    /// Check api requests before they are handled, e.g. for a login of the embedding service.
+    /// Static assets are served without checks.
+    pub fn auth<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Parts, IpAddr) -> Option<Response> + Send + Sync + 'static,
+    {
+        self.auth = Some(Arc::new(hook));
+        self
+    }
+
+    /// Serve frontend assets from a directory, falling back to the embedded assets for files that
+    /// do not exist in it. Files are read on every request without caching.
+    pub fn assets_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.assets_dir = Some(dir.into());
+        self
+    }
+
+    /// Set the security headers of frontend responses. Defaults to a strict policy that denies
+    /// framing.
+    pub fn security_headers(mut self, security_headers: SecurityHeaders) -> Self {
+        self.security_headers = security_headers;
+        self
+    }
+
+    /// Serve all routes under a path prefix, e.g. `/tools/planc`, for hosting behind a shared
+    /// ingress.
+    pub fn base_path(mut self, base_path: &str) -> Self {
+        let base_path = base_path.trim_matches('/');
+        self.base_path = if base_path.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", base_path)
+        };
+        self
+    }
+
+    pub fn build(self) -> PlancServer {
+        PlancServer {
+            ctx: Arc::new(ServiceContext::new(self.config)),
+            http: Arc::new(self.http_config),
+            sse_clients: Arc::default(),
+            auth: self.auth,
+            assets: web::Assets::new(self.assets_dir, &self.base_path),
+            security_headers: self.security_headers,
+            base_path: self.base_path,
+        }
+    }
+}
+
+/// Service for the requests of a single connection.
+///
+/// Websocket upgrades require the connection to be served with upgrades enabled, and over HTTP/2
+/// with the extended CONNECT protocol enabled.
+#[derive(Clone)]
+pub struct PlancService {
+    server: PlancServer,
+    peer_addr: SocketAddr,
+}
+
+impl<B> hyper::service::Service<hyper::Request<B>> for PlancService
+where
+    B: hyper::body::Body<Data = Bytes> + Send + 'static,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response>> + Send>>;
+
+    fn call(&self, req: hyper::Request<B>) -> Self::Future {
+        let method = req.method().as_str();
+        let path = req.uri().path();
+        let peer_addr = self.peer_addr.to_string();
+        let forwarded_for = req
+            .headers()
+            .get("x-forwarded-for")
+            .map(|value| value.to_str().unwrap_or_default())
+            .unwrap_or_default();
+        let client_addr = self
+            .server
+            .http
+            .client_addr(self.peer_addr.ip(), forwarded_for);
+        ::tracing::info!(
+            method,
+            path,
+            peer_addr,
+            client_addr = client_addr.to_string(),
+            "incoming_request"
+        );
+        let req = req.map(|body| body.map_err(Error::from).boxed_unsync());
+        let server = self.server.clone();
+        let peer_ip = self.peer_addr.ip();
+        Box::pin(async move { route_request(req, server, peer_ip, client_addr).await })
+    }
+}
+
+async fn route_request(
+    req: Request,
+    server: PlancServer,
+    peer_addr: IpAddr,
+    client_addr: IpAddr,
+) -> Result<Response> {
+    let mut req = req;
+    if let Some(response) = strip_base_path(&mut req, &server.base_path)? {
+        return Ok(response);
+    }
+    let path = req.uri().path();
+    assert!(path.starts_with('/'));
+
+    match path[1..].split('/').next() {
+        Some("api") => {
+            let (parts, body) = req.into_parts();
+            if let Some(auth) = &server.auth {
+                if let Some(response) = auth(&parts, client_addr) {
+                    ::tracing::warn!(%client_addr, "auth_denied");
+                    return Ok(response);
+                }
+            }
+            let req = Request::from_parts(parts, body);
+            api::route_request(
+                req,
+                server.ctx,
+                server.http,
+                server.sse_clients,
+                peer_addr,
+                client_addr,
+            )
+            .await
+        }
+        _ => web::route_request(req, &server.assets, &server.security_headers).await,
+    }
+}
+
+/// Remove the base path from the request path so routing works as if served at the root.
+///
+/// Returns a response for requests outside of the base path (404), and for the base path without
+/// trailing slash, which is redirected so relative URLs in the frontend resolve correctly.
+fn strip_base_path(req: &mut Request, base_path: &str) -> Result<Option<Response>> {
+    if base_path.is_empty() {
+        return Ok(None);
+    }
+    let path = req.uri().path();
+    let stripped = match path.strip_prefix(base_path) {
+        Some("") => {
+            let response = hyper::Response::builder()
+                .status(hyper::StatusCode::PERMANENT_REDIRECT)
+                .header(hyper::header::LOCATION, format!("{}/", base_path))
+                .body(Body::default())?;
+            return Ok(Some(response));
+        }
+        Some(stripped) if stripped.starts_with('/') => stripped,
+        _ => {
+            let response = hyper::Response::builder()
+                .status(hyper::StatusCode::NOT_FOUND)
+                .body(full_body("NOT FOUND"))?;
+            return Ok(Some(response));
+        }
+    };
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{}?{}", stripped, query),
+        None => stripped.to_string(),
+    };
+    let mut uri_parts = req.uri().clone().into_parts();
+    uri_parts.path_and_query = Some(path_and_query.parse()?);
+    *req.uri_mut() = hyper::Uri::from_parts(uri_parts)?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use hyper::StatusCode;
+    use planc_client::{Client, Encoding, VoteStatus};
+    use planc_core::{testing, ServerMessage, SessionState};
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Start a server with the test configuration and return its address.
+    async fn start_server(builder: PlancServerBuilder) -> SocketAddr {
+        spawn_server(builder.config(testing::test_config()).build()).await
+    }
+
+    /// Serve on an ephemeral port and return the address.
+    async fn spawn_server(server: PlancServer) -> SocketAddr {
+        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp_listener.local_addr().unwrap();
+        tokio::spawn(async move { server.serve(tcp_listener).await });
+        addr
+    }
+
+    async fn join(addr: SocketAddr, session_id: &str, name: &str, encoding: Encoding) -> Client {
+        let url = format!("ws://{}/api/{}", addr, session_id);
+        let mut client = Client::connect(&url, encoding).await.unwrap();
+        client.set_name(name).await.unwrap();
+        client
+            .state_where(|state| {
+                state
+                    .users
+                    .values()
+                    .any(|user| user.name.as_deref() == Some(name))
+            })
+            .await
+            .unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn e2e_voting_test() {
+        let addr = start_server(PlancServer::builder()).await;
+        let mut alice = join(addr, "e2e", "alice", Encoding::Json).await;
+        let mut bob = join(addr, "e2e", "bob", Encoding::MessagePack).await;
+        let alice_id = alice.whoami().await.unwrap();
+
+        alice.set_points("3").await.unwrap();
+        let state = bob
+            .state_where(|state| state.user(&alice_id).unwrap().vote_status == VoteStatus::Voted)
+            .await
+            .unwrap();
+        assert!(!state.revealed);
+
+        bob.set_points("5").await.unwrap();
+        let state = alice.state_where(|state| state.revealed).await.unwrap();
+        let points: Vec<_> = state
+            .ordered_users()
+            .into_iter()
+            .map(|user| user.vote_status.clone())
+            .collect();
+        assert_eq!(
+            points,
+            vec![
+                VoteStatus::Revealed("3".to_string()),
+                VoteStatus::Revealed("5".to_string())
+            ]
+        );
+
+        bob.close().await.unwrap();
+        alice
+            .state_where(|state| state.users.len() == 1)
+            .await
+            .unwrap();
+    }
+
+    /// Send a request over a new HTTP/1 connection.
+    async fn send_request(
+        addr: SocketAddr,
+        req: hyper::Request<Body>,
+    ) -> hyper::Response<hyper::body::Incoming> {
+        let tcp_stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, connection) =
+            hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(tcp_stream))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+        sender.send_request(req).await.unwrap()
+    }
+
+    /// Post a message body with a bearer token and return the response status.
+    async fn post_message(
+        addr: SocketAddr,
+        session_id: &str,
+        token: &str,
+        body: &str,
+    ) -> StatusCode {
+        let req = hyper::Request::post(format!("/api/{}/messages", session_id))
+            .header("host", addr.to_string())
+            .header("authorization", format!("Bearer {}", token))
+            .body(full_body(body.to_string()))
+            .unwrap();
+        send_request(addr, req).await.status()
+    }
+
+    /// Client side of a Server-Sent Events stream.
+    struct EventStream {
+        body: hyper::body::Incoming,
+        buffer: String,
+    }
+
+    impl EventStream {
+        async fn open(addr: SocketAddr, session_id: &str) -> Self {
+            let req = hyper::Request::get(format!("/api/{}/events", session_id))
+                .header("host", addr.to_string())
+                .body(Body::default())
+                .unwrap();
+            let response = send_request(addr, req).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            Self {
+                body: response.into_body(),
+                buffer: String::new(),
+            }
+        }
+
+        /// Receive the next event as event type and data.
+        async fn next(&mut self) -> (String, String) {
+            loop {
+                if let Some(end) = self.buffer.find("\n\n") {
+                    let event: String = self.buffer.drain(..end + 2).collect();
+                    let mut event_type = "message".to_string();
+                    let mut data = String::new();
+                    for line in event.lines() {
+                        if let Some(value) = line.strip_prefix("event: ") {
+                            event_type = value.to_string();
+                        } else if let Some(value) = line.strip_prefix("data: ") {
+                            data = value.to_string();
+                        }
+                    }
+                    return (event_type, data);
+                }
+                let frame = tokio::time::timeout(Duration::from_secs(5), self.body.frame())
+                    .await
+                    .expect("Timed out waiting for an event")
+                    .expect("Event stream ended")
+                    .unwrap();
+                if let Ok(data) = frame.into_data() {
+                    self.buffer.push_str(std::str::from_utf8(&data).unwrap());
+                }
+            }
+        }
+
+        /// Receive states until one matches the predicate.
+        async fn state_where(&mut self, predicate: impl Fn(&SessionState) -> bool) -> SessionState {
+            loop {
+                let (event_type, data) = self.next().await;
+                assert_eq!(event_type, "message");
+                if let ServerMessage::State(state) = serde_json::from_str(&data).unwrap() {
+                    if predicate(&state) {
+                        return state;
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn sse_test() {
+        let addr = start_server(PlancServer::builder()).await;
+        let mut alice = join(addr, "sse", "alice", Encoding::Json).await;
+
+        let mut events = EventStream::open(addr, "sse").await;
+        let (event_type, token) = events.next().await;
+        assert_eq!(event_type, "token");
+
+        let name_change = r#"{"tag":"NameChange","content":"bob"}"#;
+        assert_eq!(
+            post_message(addr, "sse", &token, name_change).await,
+            StatusCode::NO_CONTENT
+        );
+        let has_bob = |state: &SessionState| {
+            state
+                .users
+                .values()
+                .any(|user| user.name.as_deref() == Some("bob"))
+        };
+        events.state_where(has_bob).await;
+        alice.state_where(has_bob).await.unwrap();
+
+        // Tokens are only valid for their own session.
+        assert_eq!(
+            post_message(addr, "sse", "unknown", name_change).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            post_message(addr, "other", &token, name_change).await,
+            StatusCode::UNAUTHORIZED
+        );
+        let too_large = "x".repeat(32 << 10);
+        assert_eq!(
+            post_message(addr, "sse", &token, &too_large).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            post_message(addr, "sse", &token, "{").await,
+            StatusCode::BAD_REQUEST
+        );
+
+        // Dropping the event stream leaves the session.
+        drop(events);
+        alice
+            .state_where(|state| state.users.len() == 1)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sse_gone_test() {
+        let server = PlancServer::builder()
+            .config(ServiceContextConfig {
+                disconnect_grace_period: Duration::from_secs(5),
+                ..testing::test_config()
+            })
+            .build();
+        let addr = spawn_server(server).await;
+
+        let mut events = EventStream::open(addr, "sse").await;
+        let (_, token) = events.next().await;
+        drop(events);
+
+        // The token stays registered during the grace period, but nobody reads the messages.
+        let whoami = r#"{"tag":"Whoami","content":null}"#;
+        let status = loop {
+            let status = post_message(addr, "sse", &token, whoami).await;
+            if status != StatusCode::NO_CONTENT {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(status, StatusCode::GONE);
+    }
+
+    #[tokio::test]
+    async fn auth_hook_test() {
+        let builder = PlancServer::builder().auth(|parts, _| {
+            if parts.uri.path().starts_with("/api/private") {
+                api::error_response(StatusCode::UNAUTHORIZED, "Login required").ok()
+            } else {
+                None
+            }
+        });
+        let addr = start_server(builder).await;
+
+        let url = format!("ws://{}/api/private", addr);
+        let err = Client::connect(&url, Encoding::Json).await.err().unwrap();
+        assert!(err.to_string().contains("401"), "{}", err);
+        join(addr, "public", "alice", Encoding::Json).await;
+    }
+
+    #[tokio::test]
+    async fn http2_test() {
+        use tokio_tungstenite::tungstenite::protocol::Role;
+        use tokio_tungstenite::WebSocketStream;
+
+        let addr = start_server(PlancServer::builder()).await;
+        let tcp_stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut sender, connection) = hyper::client::conn::http2::handshake(
+            TokioExecutor::new(),
+            hyper_util::rt::TokioIo::new(tcp_stream),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(connection);
+
+        let req = hyper::Request::get(format!("http://{}/", addr))
+            .body(Body::default())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), hyper::Version::HTTP_2);
+
+        // Websocket over extended CONNECT.
+        let mut req = hyper::Request::connect(format!("http://{}/api/h2", addr))
+            .header("sec-websocket-version", "13")
+            .body(Body::default())
+            .unwrap();
+        req.extensions_mut()
+            .insert(hyper::ext::Protocol::from_static("websocket"));
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let upgraded = hyper::upgrade::on(response).await.unwrap();
+        let mut websocket = WebSocketStream::from_raw_socket(
+            hyper_util::rt::TokioIo::new(upgraded),
+            Role::Client,
+            None,
+        )
+        .await;
+        let message = websocket.next().await.unwrap().unwrap();
+        assert!(
+            message.to_text().unwrap().contains("\"State\""),
+            "{}",
+            message
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket_test() {
+        let path = std::env::temp_dir().join(format!("planc-{}.sock", std::process::id()));
+        // The socket file of a previous run is replaced.
+        drop(Listener::bind_unix(&path).unwrap());
+        let listener = Listener::bind_unix(&path).unwrap();
+        let server = PlancServer::builder()
+            .config(testing::test_config())
+            .build();
+        tokio::spawn(async move { server.serve(listener).await });
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (mut sender, connection) =
+            hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+        let req = hyper::Request::get("/")
+            .header("host", "localhost")
+            .body(Body::default())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        std::fs::remove_file(&path).unwrap();
+
+        // Connections are rate limited separately.
+        let (first, second) = (unix_peer_addr(), unix_peer_addr());
+        assert_ne!(first, second);
+        assert!(first.ip().is_loopback() && second.ip().is_loopback());
+    }
+
+    #[tokio::test]
+    async fn base_path_test() {
+        let addr = start_server(PlancServer::builder().base_path("/tools/planc/")).await;
+
+        let url = format!("ws://{}/api/abcd", addr);
+        let err = Client::connect(&url, Encoding::Json).await.err().unwrap();
+        assert!(err.to_string().contains("404"), "{}", err);
+        let url = format!("ws://{}/tools/planc/api/abcd", addr);
+        Client::connect(&url, Encoding::Json).await.unwrap();
+    }
+
+    #[test]
+    fn strip_base_path_test() {
+        let strip = |uri: &str| {
+            let mut req = hyper::Request::builder()
+                .uri(uri)
+                .body(Body::default())
+                .unwrap();
+            let response = strip_base_path(&mut req, "/tools/planc").unwrap();
+            (
+                req.uri().clone(),
+                response.map(|response| response.status()),
+            )
+        };
+        let (uri, response) = strip("/tools/planc/api/abcd?x=1");
+        assert_eq!(
+            (uri.to_string().as_str(), response),
+            ("/api/abcd?x=1", None)
+        );
+        let (_, response) = strip("/tools/planc");
+        assert_eq!(response, Some(StatusCode::PERMANENT_REDIRECT));
+        let (_, response) = strip("/tools/plancx/");
+        assert_eq!(response, Some(StatusCode::NOT_FOUND));
+        let (_, response) = strip("/api/abcd");
+        assert_eq!(response, Some(StatusCode::NOT_FOUND));
+    }
+}
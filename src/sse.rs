@@ -0,0 +1,157 @@
+//! Server-Sent Events transport for clients behind proxies that break websockets.
+//!
+//! A client opens '/api/<session_id>/events' to join the session. The first event carries a token
+//! which the client passes as bearer token when posting messages to '/api/<session_id>/messages'.
+//! All other events are JSON encoded `ServerMessage`s.
+
+use crate::{api, Body, Request, Response};
+use anyhow::Result;
+use futures::channel::{mpsc, oneshot};
+use futures::{future, stream, SinkExt, StreamExt, TryStreamExt};
+use http_body_util::BodyExt;
+use http_body_util::{Limited, StreamBody};
+use hyper::body::Bytes;
+use hyper::body::Frame;
+use hyper::StatusCode;
+use planc_core::{
+    ChannelTransport, ClientMessage, Connection, MessageSink, MessageStream, ServerMessage,
+    ServiceContext, Transport,
+};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Number of posted messages that may be queued for a client.
+const MESSAGE_QUEUE_SIZE: usize = 8;
+
+/// Message channels of SSE clients by token, together with their session id.
+#[derive(Default)]
+pub struct SseClients {
+    clients: Mutex<HashMap<String, (String, mpsc::Sender<ClientMessage>)>>,
+}
+
+impl SseClients {
+    /// Register the message channel of a client and return its token.
+    fn register(&self, session_id: &str, channel: mpsc::Sender<ClientMessage>) -> Result<String> {
+        let mut token = [0u8; 16];
+        getrandom::fill(&mut token)?;
+        let token: String = token.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.clients
+            .lock()
+            .unwrap()
+            .insert(token.clone(), (session_id.to_string(), channel));
+        Ok(token)
+    }
+
+    /// Get the message channel of a client in a session.
+    fn get(&self, session_id: &str, token: &str) -> Option<mpsc::Sender<ClientMessage>> {
+        let clients = self.clients.lock().unwrap();
+        match clients.get(token) {
+            Some((client_session_id, channel)) if client_session_id == session_id => {
+                Some(channel.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn unregister(&self, token: &str) {
+        self.clients.lock().unwrap().remove(token);
+    }
+}
+
+/// Transport sending messages as Server-Sent Events and receiving messages posted over HTTP.
+pub struct SseTransport {
+    channel: ChannelTransport,
+    closed: oneshot::Receiver<()>,
+}
+
+impl Transport for SseTransport {
+    fn split(self) -> (MessageStream, MessageSink) {
+        let (stream, sink) = self.channel.split();
+        (stream.take_until(self.closed).boxed(), sink)
+    }
+}
+
+pub fn events(
+    ctx: Arc<ServiceContext>,
+    sse_clients: Arc<SseClients>,
+    client_addr: IpAddr,
+    session_id: String,
+) -> Result<Response> {
+    let (outgoing_tx, outgoing_rx) = mpsc::channel(0);
+    let (incoming_tx, incoming_rx) = mpsc::channel(MESSAGE_QUEUE_SIZE);
+    let token = sse_clients.register(&session_id, incoming_tx)?;
+
+    // The response body is dropped when the client goes away, which cancels `closed` and ends the
+    // incoming stream so the user leaves the session.
+    let (closed_tx, closed) = oneshot::channel::<()>();
+    let token_event = stream::once(future::ready(Ok(format!(
+        "event: token\ndata: {}\n\n",
+        token
+    ))));
+    let message_events = outgoing_rx.map(move |msg: ServerMessage| {
+        let _closed_tx = &closed_tx;
+        Ok(format!("data: {}\n\n", serde_json::to_string(&msg)?))
+    });
+    let body = StreamBody::new(
+        token_event
+            .chain(message_events)
+            .map_ok(|event| Frame::data(Bytes::from(event))),
+    );
+
+    let transport = SseTransport {
+        channel: ChannelTransport::new(incoming_rx, outgoing_tx),
+        closed,
+    };
+    tokio::spawn(async move {
+        let connection = Connection::new(transport);
+        if let Err(err) = ctx.join_session(client_addr, &session_id, connection).await {
+            ::tracing::warn!(?err, "sse_events");
+        }
+        sse_clients.unregister(&token);
+    });
+
+    Ok(hyper::Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("X-Accel-Buffering", "no")
+        .body(BodyExt::boxed_unsync(body))?)
+}
+
+pub async fn post_message(
+    req: Request,
+    sse_clients: &SseClients,
+    max_message_size: usize,
+    session_id: String,
+) -> Result<Response> {
+    let token = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    let mut channel = match sse_clients.get(&session_id, token) {
+        Some(channel) => channel,
+        None => return api::error_response(StatusCode::UNAUTHORIZED, "Unknown token"),
+    };
+
+    let body = match Limited::new(req.into_body(), max_message_size)
+        .collect()
+        .await
+    {
+        Ok(body) => body.to_bytes(),
+        Err(_) => return api::error_response(StatusCode::PAYLOAD_TOO_LARGE, "Message too large"),
+    };
+    let msg: ClientMessage = match serde_json::from_slice(&body) {
+        Ok(msg) => msg,
+        Err(_) => return api::error_response(StatusCode::BAD_REQUEST, "Invalid message"),
+    };
+
+    if channel.send(msg).await.is_err() {
+        return api::error_response(StatusCode::GONE, "Client disconnected");
+    }
+    Ok(hyper::Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::default())?)
+}
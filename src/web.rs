@@ -1,46 +1,446 @@
-use super::*;
-use hyper::{body::Bytes, StatusCode};
+use crate::security::SecurityHeaders;
+use crate::{full_body, Body, Request, Response};
+use anyhow::Result;
+use hyper::body::Bytes;
+use hyper::header::{self, HeaderMap};
+use hyper::StatusCode;
 use include_dir::{include_dir, Dir};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
-pub async fn route_request(req: Request) -> Result<Response> {
-    const WEB_DIR: Dir = include_dir!("web/dist");
+const WEB_DIR: Dir = include_dir!("web/dist");
 
-    let uri = req.uri();
-    assert!(uri.path().starts_with('/'));
-    let path = &uri.path()[1..];
+/// Directory of the frontend build output whose file names contain a content hash.
+const HASHED_DIR: &str = "assets/";
+
+/// Cache policy for embedded build output with a content hash in its name, which never changes.
+const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
 
-    let response = WEB_DIR
-        .get_file(path)
-        .map(|file| {
-            // Explicit request for an existing file.
-            let content_type = match path.rsplit('.').next().unwrap() {
-                "html" => "text/html",
-                "js" => "text/javascript",
-                "css" => "text/css",
-                "txt" => "text/plain",
-                _ => "application/octet-stream",
-            };
-            hyper::Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", content_type)
-                .body(Full::new(Bytes::from(file.contents())))
+/// Cache policy for all other assets. Clients revalidate with the ETag.
+const CACHE_REVALIDATE: &str = "no-cache";
+
+/// Precompressed variants of an asset, in order of preference.
+const ENCODINGS: [(&str, &str); 2] = [("br", ".br"), ("gzip", ".gz")];
+
+/// Frontend assets.
+///
+/// Assets are embedded at compile time. Files in an optional directory on disk take precedence, so
+/// a custom frontend can be served without rebuilding. Files on disk are served without caching:
+/// they are read on every request, so changes show up on the next request. There is no file
+/// watching or live reload.
+#[derive(Debug, Clone, Default)]
+pub struct Assets {
+    dir: Option<PathBuf>,
+    /// Path the frontend is served under, without trailing slash.
+    base_path: String,
+    /// ETag of the embedded index.html with the base path injected.
+    index_etag: Arc<OnceLock<String>>,
+}
+
+/// Contents of an asset with its ETag.
+#[derive(Debug, PartialEq, Eq)]
+struct Asset {
+    contents: Bytes,
+    etag: String,
+    /// Whether the asset is embedded in the binary rather than read from disk.
+    embedded: bool,
+}
+
+impl Assets {
+    pub fn new(dir: Option<PathBuf>, base_path: &str) -> Self {
+        Self {
+            dir,
+            base_path: base_path.to_string(),
+            index_etag: Arc::default(),
+        }
+    }
+
+    /// Get index.html with the base path injected, so the frontend can build its URLs.
+    async fn index(&self) -> Option<Asset> {
+        let (contents, embedded) = self.read("index.html").await?;
+        let html = String::from_utf8_lossy(&contents);
+        let base_path = html_escape(&format!("{}/", self.base_path));
+        let tags = format!(
+            "<base href=\"{0}\"><meta name=\"planc-base-path\" content=\"{0}\">",
+            base_path
+        );
+        let html = match html.find("<head>") {
+            Some(index) => {
+                let index = index + "<head>".len();
+                format!("{}{}{}", &html[..index], tags, &html[index..])
+            }
+            None => format!("{}{}", tags, html),
+        };
+        let etag = if embedded {
+            self.index_etag
+                .get_or_init(|| etag(html.as_bytes()))
+                .clone()
+        } else {
+            etag(html.as_bytes())
+        };
+        Some(Asset {
+            contents: html.into(),
+            etag,
+            embedded,
         })
-        .or_else(|| {
-            // Fallback path just returns index.html so we can handle most routing in the frontend.
-            WEB_DIR.get_file("index.html").map(|file| {
-                hyper::Response::builder()
-                    .status(StatusCode::OK)
-                    .header("Content-Type", "text/html")
-                    .body(Full::new(Bytes::from(file.contents())))
-            })
+    }
+
+    /// Get a file by its path relative to the asset root.
+    async fn get(&self, path: &str) -> Option<Asset> {
+        let (contents, embedded) = self.read(path).await?;
+        let etag = match embedded_etags().get(Path::new(path)) {
+            Some(etag) if embedded => etag.clone(),
+            _ => etag(&contents),
+        };
+        Some(Asset {
+            contents,
+            etag,
+            embedded,
         })
-        .unwrap_or_else(|| {
-            // Fallback for when index.html does not exist, which may happen in some development
-            // setups.
-            hyper::Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Full::new(Bytes::from("NOT FOUND")))
-        })?;
+    }
+
+    /// Read the contents of a file and whether it is embedded.
+    async fn read(&self, path: &str) -> Option<(Bytes, bool)> {
+        if let Some(dir) = &self.dir {
+            let file_path = safe_path(path).map(|path| dir.join(path));
+            if let Some(file_path) = file_path {
+                match tokio::fs::read(&file_path).await {
+                    Ok(contents) => return Some((contents.into(), false)),
+                    // Directories like `assets/` fall back like missing files.
+                    Err(err)
+                        if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::IsADirectory) => {}
+                    Err(err) => ::tracing::warn!(?err, ?file_path, "Assets::get"),
+                }
+            }
+        }
+        WEB_DIR
+            .get_file(path)
+            .map(|file| (Bytes::from_static(file.contents()), true))
+    }
+
+    /// Get a file in the best encoding accepted by the client.
+    ///
+    /// Precompressed variants are files with an additional `.br` or `.gz` extension next to the
+    /// original file. Returns the asset and the content encoding, if any.
+    async fn get_encoded(
+        &self,
+        path: &str,
+        accept_encoding: &str,
+    ) -> Option<(Asset, Option<&'static str>)> {
+        for (encoding, extension) in ENCODINGS {
+            if accepts_encoding(accept_encoding, encoding) {
+                if let Some(asset) = self.get(&format!("{}{}", path, extension)).await {
+                    return Some((asset, Some(encoding)));
+                }
+            }
+        }
+        self.get(path).await.map(|asset| (asset, None))
+    }
+}
+
+/// ETags of all embedded files by path, computed on first use.
+fn embedded_etags() -> &'static HashMap<&'static Path, String> {
+    fn add_files(dir: &'static Dir<'static>, etags: &mut HashMap<&'static Path, String>) {
+        for file in dir.files() {
+            etags.insert(file.path(), etag(file.contents()));
+        }
+        for dir in dir.dirs() {
+            add_files(dir, etags);
+        }
+    }
+    static EMBEDDED_ETAGS: OnceLock<HashMap<&'static Path, String>> = OnceLock::new();
+    EMBEDDED_ETAGS.get_or_init(|| {
+        let mut etags = HashMap::new();
+        add_files(&WEB_DIR, &mut etags);
+        etags
+    })
+}
+
+/// Check that a request path stays within the asset root.
+fn safe_path(path: &str) -> Option<&Path> {
+    let path = Path::new(path);
+    let is_safe = path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    is_safe.then_some(path)
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Content type of a file by its extension.
+fn content_type(path: &str) -> &'static str {
+    let extension = match path.rsplit_once('.') {
+        Some((_, extension)) => extension.to_ascii_lowercase(),
+        None => return "application/octet-stream",
+    };
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "xml" => "application/xml",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Check whether a client accepts a content encoding according to its accept-encoding header.
+fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|entry| {
+        let mut params = entry.split(';').map(str::trim);
+        let name = params.next().unwrap_or_default();
+        let quality = params
+            .find_map(|param| param.strip_prefix("q="))
+            .and_then(|quality| quality.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        (name.eq_ignore_ascii_case(encoding) || name == "*") && quality > 0.0
+    })
+}
+
+/// Strong entity tag derived from the contents.
+fn etag(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    let hex: String = digest[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("\"{}\"", hex)
+}
+
+/// Check whether the client already has the current version according to its if-none-match
+/// header. Uses weak comparison as required for conditional GET requests.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
 
+pub async fn route_request(
+    req: Request,
+    assets: &Assets,
+    security_headers: &SecurityHeaders,
+) -> Result<Response> {
+    let mut response = asset_response(req, assets).await?;
+    security_headers.apply(response.headers_mut())?;
     Ok(response)
 }
+
+async fn asset_response(req: Request, assets: &Assets) -> Result<Response> {
+    let uri = req.uri();
+    assert!(uri.path().starts_with('/'));
+    let path = &uri.path()[1..];
+    let header = |headers: &HeaderMap, name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let accept_encoding = header(req.headers(), header::ACCEPT_ENCODING);
+    let if_none_match = header(req.headers(), header::IF_NONE_MATCH);
+
+    // Explicit request for an existing file. The fallback path just returns index.html so we can
+    // handle most routing in the frontend. index.html is always modified, so precompressed variants
+    // of it cannot be used.
+    let found = match path {
+        "index.html" => None,
+        _ => assets.get_encoded(path, &accept_encoding).await,
+    };
+    let (path, (asset, encoding)) = match found {
+        Some(found) => (path, found),
+        None => match assets.index().await {
+            Some(asset) => ("index.html", (asset, None)),
+            None => {
+                // Fallback for when index.html does not exist, which may happen in some
+                // development setups.
+                return Ok(hyper::Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(full_body("NOT FOUND"))?);
+            }
+        },
+    };
+
+    // Only the embedded build output is known to have hashed names. Files on disk may be
+    // replaced under the same name.
+    let cache_control = if asset.embedded && path.starts_with(HASHED_DIR) {
+        CACHE_IMMUTABLE
+    } else {
+        CACHE_REVALIDATE
+    };
+    let mut response = hyper::Response::builder()
+        .header(header::ETAG, &asset.etag)
+        .header(header::CACHE_CONTROL, cache_control)
+        .header(header::VARY, "Accept-Encoding");
+    if etag_matches(&if_none_match, &asset.etag) {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::default())?);
+    }
+    if let Some(encoding) = encoding {
+        response = response.header(header::CONTENT_ENCODING, encoding);
+    }
+    Ok(response
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type(path))
+        .body(full_body(asset.contents))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_path_test() {
+        assert_eq!(safe_path("index.html"), Some(Path::new("index.html")));
+        assert_eq!(safe_path("assets/app.js"), Some(Path::new("assets/app.js")));
+        assert_eq!(safe_path(""), None);
+        assert_eq!(safe_path("../secret"), None);
+        assert_eq!(safe_path("assets/../../secret"), None);
+        assert_eq!(safe_path("/etc/passwd"), None);
+    }
+
+    #[test]
+    fn headers_test() {
+        assert_eq!(content_type("index.html"), "text/html; charset=utf-8");
+        assert_eq!(content_type("assets/logo.SVG"), "image/svg+xml");
+        assert_eq!(
+            content_type("site.webmanifest"),
+            "application/manifest+json"
+        );
+        assert_eq!(content_type("LICENSE"), "application/octet-stream");
+
+        assert!(accepts_encoding("gzip, deflate, br", "br"));
+        assert!(accepts_encoding("*", "gzip"));
+        assert!(!accepts_encoding("gzip;q=1.0, br;q=0", "br"));
+        assert!(!accepts_encoding("", "gzip"));
+
+        let etag = etag(b"contents");
+        assert!(etag_matches(&etag, &etag));
+        assert!(etag_matches(&format!("\"other\", W/{}", etag), &etag));
+        assert!(etag_matches("*", &etag));
+        assert!(!etag_matches("\"other\"", &etag));
+        assert!(!etag_matches("", &etag));
+    }
+
+    #[tokio::test]
+    async fn assets_override_test() {
+        let dir = std::env::temp_dir().join(format!("planc-assets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("brand.css"), "body {}").unwrap();
+
+        let assets = Assets::new(Some(dir.clone()), "");
+        let contents = |asset: Option<Asset>| asset.map(|asset| asset.contents);
+        let asset = assets.get("brand.css").await.unwrap();
+        assert_eq!(asset.contents, Bytes::from("body {}"));
+        assert_eq!(asset.etag, etag(b"body {}"));
+        assert!(!asset.embedded);
+        // Embedded files are used if there is no file on disk.
+        let asset = assets.get("index.html").await.unwrap();
+        assert_eq!(
+            Some(&asset),
+            Assets::default().get("index.html").await.as_ref()
+        );
+        assert_eq!(asset.etag, etag(&asset.contents));
+        assert!(asset.embedded);
+        assert_eq!(assets.get("missing.js").await, None);
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        assert_eq!(assets.get("assets").await, None);
+
+        // Changes are picked up without recreating the assets.
+        std::fs::write(dir.join("brand.css"), "body { color: red }").unwrap();
+        assert_eq!(
+            contents(assets.get("brand.css").await),
+            Some(Bytes::from("body { color: red }"))
+        );
+
+        // Precompressed variants are preferred if the client accepts them.
+        std::fs::write(dir.join("brand.css.br"), "compressed").unwrap();
+        let (asset, encoding) = assets.get_encoded("brand.css", "gzip, br").await.unwrap();
+        assert_eq!(
+            (asset.contents, encoding),
+            (Bytes::from("compressed"), Some("br"))
+        );
+        let (asset, encoding) = assets.get_encoded("brand.css", "gzip").await.unwrap();
+        assert_eq!(
+            (asset.contents, encoding),
+            (Bytes::from("body { color: red }"), None)
+        );
+
+        // Only embedded build output is cached indefinitely.
+        std::fs::write(dir.join("assets/index-BwD3x9_k.js"), "").unwrap();
+        let request = hyper::Request::builder()
+            .uri("/assets/index-BwD3x9_k.js")
+            .body(Body::default())
+            .unwrap();
+        let response = asset_response(request, &assets).await.unwrap();
+        assert_eq!(response.headers()[header::CACHE_CONTROL], CACHE_REVALIDATE);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn index_test() {
+        let dir = std::env::temp_dir().join(format!("planc-index-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("index.html"),
+            "<html><head><title>x</title></head></html>",
+        )
+        .unwrap();
+
+        let assets = Assets::new(Some(dir.clone()), "/tools/\"planc");
+        assert_eq!(
+            assets.index().await.unwrap().contents,
+            "<html><head><base href=\"/tools/&quot;planc/\">\
+             <meta name=\"planc-base-path\" content=\"/tools/&quot;planc/\"><title>x</title></head></html>"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn conditional_request_test() {
+        let assets = Assets::default();
+        let request = |if_none_match: &str| {
+            hyper::Request::builder()
+                .uri("/some/frontend/route")
+                .header(header::IF_NONE_MATCH, if_none_match)
+                .body(Body::default())
+                .unwrap()
+        };
+
+        let response = asset_response(request(""), &assets).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(response.headers()[header::CACHE_CONTROL], CACHE_REVALIDATE);
+        let etag = response.headers()[header::ETAG].to_str().unwrap();
+
+        let response = asset_response(request(etag), &assets).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+    }
+}
@@ -0,0 +1,285 @@
+use crate::codec::Codec;
+use anyhow::Error;
+use futures::channel::{mpsc, oneshot};
+use futures::{future, SinkExt, StreamExt, TryStreamExt};
+use planc_core::{MessageSink, MessageStream, PlancError, ServerMessage, Transport};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Error as WebSocketError;
+use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+use tokio_tungstenite::tungstenite::Utf8Bytes;
+use tokio_tungstenite::WebSocketStream;
+
+/// Shortest interval between pings, so a zero interval does not flood the peer.
+const MIN_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Transport over a websocket.
+pub struct WebSocketTransport<S> {
+    socket: WebSocketStream<S>,
+    codec: Codec,
+    ping_interval: Duration,
+    pong_timeout: Duration,
+}
+
+impl<S> WebSocketTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    /// Create a transport from a websocket.
+    ///
+    /// Messages are encoded with `codec`. A websocket ping is sent `ping_interval` after the peer
+    /// answered the previous one. If nothing is received from the peer within `pong_timeout` after
+    /// a ping the connection is considered dead and terminated, so a dead peer is detected at most
+    /// `ping_interval + pong_timeout` after it was last heard from. Intervals below one second are raised to one second.
+    pub fn new(
+        socket: WebSocketStream<S>,
+        codec: Codec,
+        ping_interval: Duration,
+        pong_timeout: Duration,
+    ) -> Self {
+        Self {
+            socket,
+            codec,
+            ping_interval: ping_interval.max(MIN_PING_INTERVAL),
+            pong_timeout,
+        }
+    }
+}
+
+impl<S> Transport for WebSocketTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    fn split(self) -> (MessageStream, MessageSink) {
+        let Self {
+            socket,
+            codec,
+            ping_interval,
+            pong_timeout,
+        } = self;
+        let (mut sink, stream) = socket.split();
+
+        // Create channel to send frames. Messages as well as control frames go through it.
+        let (channel, mut receiver) = mpsc::channel(0);
+        tokio::spawn(async move {
+            while let Some(msg) = receiver.next().await {
+                match sink.send(msg).await {
+                    Ok(_) => {}
+                    Err(WebSocketError::ConnectionClosed) => break,
+                    Err(err) => {
+                        ::tracing::warn!(?err, "WebSocketTransport/sink::send");
+                        break;
+                    }
+                }
+            }
+        });
+
+        // Send pings and terminate the connection if the peer does not respond within the pong
+        // timeout. Any frame from the peer counts as a response. Closing the channel stops the sink
+        // task and the dead signal ends the stream, which drops the socket.
+        let activity = Arc::new(Notify::new());
+        let (dead_tx, dead_rx) = oneshot::channel::<()>();
+        let mut ping_channel = channel.clone();
+        let ping_activity = Arc::clone(&activity);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(ping_interval).await;
+                let response = async {
+                    let notified = ping_activity.notified();
+                    futures::pin_mut!(notified);
+                    notified.as_mut().enable();
+                    ping_channel
+                        .send(WebSocketMessage::Ping(Default::default()))
+                        .await?;
+                    notified.await;
+                    Ok::<_, mpsc::SendError>(())
+                };
+                match tokio::time::timeout(pong_timeout, response).await {
+                    Ok(Ok(())) => {}
+                    // The connection is closed.
+                    Ok(Err(_)) => break,
+                    Err(_) => {
+                        ::tracing::warn!(?pong_timeout, "WebSocketTransport/ping_timeout");
+                        ping_channel.close_channel();
+                        let _ = dead_tx.send(());
+                        break;
+                    }
+                }
+            }
+        });
+
+        // Abstract away the websocket specifics to get a stream of messages.
+        let size_error_channel = channel.clone();
+        let control_channel = channel.clone();
+        let stream = Box::pin(
+            stream
+                .take_until(dead_rx)
+                .then(move |item| {
+                    let mut close_sender = size_error_channel.clone();
+                    async move {
+                        // Tell the peer why the connection is closed if a message is too big.
+                        if let Err(WebSocketError::Capacity(err)) = &item {
+                            let frame = CloseFrame {
+                                code: CloseCode::Size,
+                                reason: Utf8Bytes::from(err.to_string()),
+                            };
+                            let _ = close_sender
+                                .send(WebSocketMessage::Close(Some(frame)))
+                                .await;
+                        }
+                        item
+                    }
+                })
+                .err_into()
+                .try_take_while(|msg| future::ready(Ok(!msg.is_close())))
+                .try_filter_map(move |msg| {
+                    let mut control_sender = control_channel.clone();
+                    activity.notify_waiters();
+                    async move {
+                        match msg {
+                            WebSocketMessage::Close(_) => unreachable!(),
+                            WebSocketMessage::Ping(data) => {
+                                control_sender.send(WebSocketMessage::Pong(data)).await?;
+                                Ok(None)
+                            }
+                            WebSocketMessage::Pong(_) => Ok(None),
+                            msg @ (WebSocketMessage::Text(_) | WebSocketMessage::Binary(_))
+                                if codec.accepts(&msg) =>
+                            {
+                                Ok(Some(codec.decode(&msg)?))
+                            }
+                            WebSocketMessage::Text(_) | WebSocketMessage::Binary(_) => {
+                                let frame = CloseFrame {
+                                    code: CloseCode::Unsupported,
+                                    reason: Utf8Bytes::from_static(
+                                        "Frame type does not match the subprotocol",
+                                    ),
+                                };
+                                control_sender
+                                    .send(WebSocketMessage::Close(Some(frame)))
+                                    .await?;
+                                Err(PlancError::InvalidMessage.into())
+                            }
+                            WebSocketMessage::Frame(_) => Ok(None),
+                        }
+                    }
+                }),
+        );
+
+        let sink = Box::pin(
+            channel
+                .sink_err_into::<Error>()
+                .with(move |msg: ServerMessage| future::ready(codec.encode(&msg))),
+        );
+        (stream, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use planc_core::{testing, Connection, Presence, ServiceContext, ServiceContextConfig};
+    use std::net::IpAddr;
+    use tokio::io::DuplexStream;
+    use tokio::time::Instant;
+    use tokio_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+
+    const PING_INTERVAL: Duration = Duration::from_secs(2);
+    const PONG_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Connect a JSON transport to a client websocket over an in-memory stream.
+    async fn connect(
+        config: WebSocketConfig,
+    ) -> (
+        WebSocketTransport<DuplexStream>,
+        WebSocketStream<DuplexStream>,
+    ) {
+        let (server, client) = tokio::io::duplex(64 << 10);
+        let server = WebSocketStream::from_raw_socket(server, Role::Server, Some(config)).await;
+        let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let transport = WebSocketTransport::new(server, Codec::Json, PING_INTERVAL, PONG_TIMEOUT);
+        (transport, client)
+    }
+
+    /// Wait for the close frame from the server and return its code.
+    async fn close_code(client: &mut WebSocketStream<DuplexStream>) -> CloseCode {
+        loop {
+            if let WebSocketMessage::Close(Some(frame)) = client.next().await.unwrap().unwrap() {
+                return frame.code;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn close_code_test() {
+        // Messages over the size limit.
+        let config = WebSocketConfig::default().max_message_size(Some(64));
+        let (transport, mut client) = connect(config).await;
+        let (mut stream, _sink) = transport.split();
+        client
+            .send(WebSocketMessage::text("x".repeat(128)))
+            .await
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+        assert_eq!(close_code(&mut client).await, CloseCode::Size);
+
+        // Binary frames on a JSON connection.
+        let (transport, mut client) = connect(WebSocketConfig::default()).await;
+        let (mut stream, _sink) = transport.split();
+        client
+            .send(WebSocketMessage::binary(vec![0x80]))
+            .await
+            .unwrap();
+        assert!(stream.next().await.unwrap().is_err());
+        assert_eq!(close_code(&mut client).await, CloseCode::Unsupported);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ping_timeout_test() {
+        // The client never reads, so it never answers pings.
+        let (transport, _client) = connect(WebSocketConfig::default()).await;
+        let (mut stream, _sink) = transport.split();
+        let start = Instant::now();
+        assert!(stream.next().await.is_none());
+        assert_eq!(start.elapsed(), PING_INTERVAL + PONG_TIMEOUT);
+
+        // Reading clients answer pings automatically and stay connected.
+        let (transport, mut client) = connect(WebSocketConfig::default()).await;
+        let (mut stream, _sink) = transport.split();
+        tokio::spawn(async move { while client.next().await.is_some() {} });
+        let result = tokio::time::timeout(PING_INTERVAL * 10, stream.next()).await;
+        assert!(result.is_err());
+
+        // The user of a dead connection is shown as disconnected.
+        let ctx = Arc::new(ServiceContext::new(ServiceContextConfig {
+            disconnect_grace_period: Duration::from_secs(3600),
+            ..testing::test_config()
+        }));
+        let mut alice = testing::TestClient::join(&ctx, "abcd", "alice").await;
+        let (transport, _client) = connect(WebSocketConfig::default()).await;
+        let session_ctx = Arc::clone(&ctx);
+        tokio::spawn(async move {
+            let client_addr = IpAddr::from([127, 0, 0, 1]);
+            let connection = Connection::new(transport);
+            session_ctx
+                .join_session(client_addr, "abcd", connection)
+                .await
+        });
+        let alice_id = alice.user_id.clone();
+        alice
+            .state_where(|state| {
+                state.users.len() == 2
+                    && state
+                        .users
+                        .values()
+                        .filter(|user| user.id != alice_id)
+                        .all(|user| user.presence == Presence::Disconnected)
+            })
+            .await;
+    }
+}
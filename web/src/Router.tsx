@@ -1,4 +1,5 @@
 import * as reactRouterDom from "react-router-dom";
+import { BASE_PATH } from "./basePath";
 import { LoginPage } from "./pages/Login.page";
 import { SessionPage } from "./pages/Session.page";
 
@@ -11,7 +12,7 @@ const router = reactRouterDom.createBrowserRouter([
     path: "/login",
     element: <LoginPage />,
   },
-]);
+], { basename: BASE_PATH });
 
 export function Router() {
   return <reactRouterDom.RouterProvider router={router} />;
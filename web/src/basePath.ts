@@ -0,0 +1,6 @@
+/**
+ * Path the app is served under, e.g. "/tools/planc/". The server injects it into index.html. Always
+ * ends with a slash.
+ */
+export const BASE_PATH: string =
+  document.querySelector<HTMLMetaElement>('meta[name="planc-base-path"]')?.content ?? "/";
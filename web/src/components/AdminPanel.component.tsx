@@ -5,8 +5,21 @@ export default function AdminPanel() {
   const sessionControl = useSessionControl();
 
   if (sessionControl.isAdmin) {
+    const settings = sessionControl.sessionState?.settings ?? { anonymousVoting: false, excludeIdleUsers: false };
     return (
       <>
+        <mc.Space h="xl" />
+        <mc.Checkbox
+          label="Anonymous voting"
+          checked={settings.anonymousVoting}
+          onChange={(event) => sessionControl.setSettings({ ...settings, anonymousVoting: event.currentTarget.checked })}
+        />
+        <mc.Space h="xs" />
+        <mc.Checkbox
+          label="Do not wait for idle users"
+          checked={settings.excludeIdleUsers}
+          onChange={(event) => sessionControl.setSettings({ ...settings, excludeIdleUsers: event.currentTarget.checked })}
+        />
         <mc.Space h="xl" />
         <mc.Button onClick={() => sessionControl.resetPoints()}>Reset Points</mc.Button>
       </>
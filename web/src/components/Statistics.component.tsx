@@ -1,5 +1,5 @@
 import * as mc from "@mantine/core";
-import { useSessionControl } from "../context/SessionControlProvider";
+import { revealedPoints, useSessionControl } from "../context/SessionControlProvider";
 
 export default function Statistics() {
   const sessionControl = useSessionControl();
@@ -14,18 +14,27 @@ export default function Statistics() {
   let highVote = Number.NEGATIVE_INFINITY;
   let numUsers = 0;
   let votesExcluded = 0;
-  for (const uid in sessionState.users) {
-    const user = sessionState.users[uid];
-    if (user.isSpectator) {
-      continue;
+  // In anonymous mode the server only sends the vote distribution.
+  const votes: [string, number][] = [];
+  if (sessionState.distribution !== null) {
+    for (const points in sessionState.distribution) {
+      votes.push([points, sessionState.distribution[points]]);
+    }
+  } else {
+    for (const user of sessionState.users) {
+      if (!user.isSpectator) {
+        votes.push([revealedPoints(user) ?? "?", 1]);
+      }
     }
-    const points = Number(user.points);
+  }
+  for (const [vote, count] of votes) {
+    const points = Number(vote);
     if (!Number.isFinite(points)) {
-      votesExcluded += 1;
+      votesExcluded += count;
       continue;
     }
-    meanVote += points; 
-    numUsers += 1;
+    meanVote += points * count;
+    numUsers += count;
     if (points < lowVote) {
       lowVote = points;
     }
@@ -35,16 +44,18 @@ export default function Statistics() {
   }
   meanVote /= numUsers;
 
-  // Determine low and high voters.
+  // Determine low and high voters. Not available in anonymous mode.
   const lowVoters = [];
   const highVoters = [];
-  for (const uid in sessionState.users) {
-    const user = sessionState.users[uid];
-    if (user.points == lowVote) {
-      lowVoters.push(user.name);
-    }
-    if (user.points == highVote) {
-      highVoters.push(user.name);
+  if (sessionState.distribution === null) {
+    for (const user of sessionState.users) {
+      const points = Number(revealedPoints(user));
+      if (points === lowVote) {
+        lowVoters.push(user.name);
+      }
+      if (points === highVote) {
+        highVoters.push(user.name);
+      }
     }
   }
 
@@ -1,11 +1,13 @@
 import * as mc from "@mantine/core";
 import * as react from "react";
-import { useSessionControl } from "../context/SessionControlProvider";
+import { revealedPoints, useSessionControl } from "../context/SessionControlProvider";
 import AdminUserActions from "./AdminUserActions";
 
 const CHECK_MARK = "✅";
 const CROSS_MARK = "❌";
 const EYE_MARK = "👁";
+const IDLE_MARK = "💤";
+const DISCONNECTED_MARK = "🔌";
 
 export default function UserTable() {
   const sessionControl = useSessionControl();
@@ -26,31 +28,36 @@ export default function UserTable() {
 
   // Setup user rows.
   const rows: react.ReactNode[] = [];
-  for (const uid in sessionState.users) {
-    const user = sessionState.users[uid];
-    const points = sessionControl.revealPoints ? user.points : "?";
+  for (const user of sessionState.users) {
+    const points = sessionControl.revealPoints ? revealedPoints(user) ?? "?" : "?";
     let voted;
     if (user.isSpectator) {
       voted = EYE_MARK;
-    } else if (user.points !== null) {
+    } else if (user.voteStatus.tag !== "NotVoted") {
       voted = CHECK_MARK;
     } else {
       voted = CROSS_MARK;
     }
+    let presence = "";
+    if (user.presence === "Idle") {
+      presence = " " + IDLE_MARK;
+    } else if (user.presence === "Disconnected") {
+      presence = " " + DISCONNECTED_MARK;
+    }
     const cells = [
-      <mc.Table.Td key="user">{user.name}</mc.Table.Td>,
+      <mc.Table.Td key="user">{user.name}{presence}</mc.Table.Td>,
       <mc.Table.Td key="voted">{voted}</mc.Table.Td>,
       <mc.Table.Td key="points">{points}</mc.Table.Td>,
     ];
     if (sessionControl.isAdmin) {
       cells.push(
         <mc.Table.Td key="admin">
-          <AdminUserActions uid={uid} />
+          <AdminUserActions uid={user.id} />
         </mc.Table.Td>
       );
     }
     rows.push(
-      <mc.Table.Tr key={uid}>
+      <mc.Table.Tr key={user.id}>
         {cells}
       </mc.Table.Tr>
     );
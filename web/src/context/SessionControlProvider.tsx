@@ -1,5 +1,6 @@
 import * as mc_notifications from "@mantine/notifications";
 import * as react from "react";
+import { BASE_PATH } from "../basePath";
 
 export interface SessionControl {
   readonly sessionId: string | undefined;
@@ -16,6 +17,7 @@ export interface SessionControl {
   claimSession(): void;
   kickUser(userId: string): void;
   setSpectator(isSpectator: boolean): void;
+  setSettings(settings: SessionSettings): void;
 }
 
 export interface Session {
@@ -24,18 +26,42 @@ export interface Session {
 }
 
 export interface SessionState {
-  readonly users: UserStateMap;
+  readonly users: UserState[];
   readonly admin: string;
+  readonly settings: SessionSettings;
+  readonly revealed: boolean;
+  readonly distribution: VoteDistribution | null;
 }
 
-export interface UserStateMap {
-  [key: string]: UserState;
+export interface SessionSettings {
+  readonly anonymousVoting: boolean;
+  readonly excludeIdleUsers: boolean;
+}
+
+export interface VoteDistribution {
+  [points: string]: number;
 }
 
 export interface UserState {
+  readonly id: string;
   readonly name: string | undefined;
-  readonly points: number | undefined;
+  readonly voteStatus: VoteStatus;
   readonly isSpectator: boolean;
+  readonly presence: Presence;
+  readonly joinOrder: number;
+  readonly joinedAt: number;
+  readonly lastActiveAt: number;
+}
+
+export type Presence = "Active" | "Idle" | "Disconnected";
+
+export type VoteStatus =
+  | { readonly tag: "NotVoted" }
+  | { readonly tag: "Voted" }
+  | { readonly tag: "Revealed"; readonly content: string };
+
+export function revealedPoints(user: UserState): string | undefined {
+  return user.voteStatus.tag === "Revealed" ? user.voteStatus.content : undefined;
 }
 
 export interface SessionControlProviderProps {
@@ -64,6 +90,9 @@ const SESSION_CONTROL_CONTEXT = react.createContext<SessionControl>({
   setSpectator: function (): void {
     throw new Error("Function not implemented.");
   },
+  setSettings: function (): void {
+    throw new Error("Function not implemented.");
+  },
   sessionId: undefined,
   userName: undefined,
   uid: undefined,
@@ -72,29 +101,40 @@ const SESSION_CONTROL_CONTEXT = react.createContext<SessionControl>({
   revealPoints: false,
 });
 
+/** A message sent to the server. */
+interface ClientMessage {
+  readonly tag: string;
+  readonly content: unknown;
+}
+
+/** Connection to a session over a websocket or the Server-Sent Events fallback. */
+interface Connection {
+  send(message: ClientMessage): void;
+  close(): void;
+}
+
+/** A message received from the server. */
+interface ServerMessage {
+  readonly tag: string;
+  readonly content: unknown;
+}
+
+interface ConnectionHandlers {
+  onOpen(connection: Connection): void;
+  onMessage(message: ServerMessage): void;
+  onClose(): void;
+}
+
 export default function SessionControlProvider({ children }: SessionControlProviderProps) {
   const [userName, setUserName] = react.useState<string | undefined>(undefined);
   const [uid, setUid] = react.useState<string | undefined>(undefined);
   const [sessionId, setSessionId] = react.useState<string | undefined>(undefined);
-  const [webSocket, setWebSocket] = react.useState<WebSocket | undefined>(undefined);
+  const [connection, setConnection] = react.useState<Connection | undefined>(undefined);
   const [sessionState, setSessionState] = react.useState<SessionState | undefined>(undefined);
   const isAdmin = uid !== undefined && uid === sessionState?.admin;
 
-  // Only reveal points if all users that are not spectators have chosen.
-  let revealPoints = true;
-  let numNonSpectators = 0;
-  for (const uid in sessionState?.users) {
-    const user = sessionState.users[uid];
-    if (!user.isSpectator) {
-      numNonSpectators += 1;
-      if (user.points === null) {
-        revealPoints = false;
-      }
-    }
-  }
-  if (numNonSpectators === 0) {
-    revealPoints = false;
-  }
+  // The server decides when all users required to vote have chosen.
+  const revealPoints = sessionState?.revealed ?? false;
 
   const sessionControl: SessionControl = {
     joinSession: (userName, sessionId) => {
@@ -102,31 +142,31 @@ export default function SessionControlProvider({ children }: SessionControlProvi
       setSessionId(sessionId);
     },
     resetSession: () => {
-      if (webSocket !== undefined) {
-        webSocket.onclose = () => {};
-        webSocket.close();
-      }
+      connection?.close();
       setUserName(undefined);
       setUid(undefined);
       setSessionId(undefined);
-      setWebSocket(undefined);
+      setConnection(undefined);
       setUid(undefined);
       setSessionState(undefined);
     },
     setPoints: (points: string) => {
-      webSocket?.send(JSON.stringify({ tag: "SetPoints", content: points }));
+      connection?.send({ tag: "SetPoints", content: points });
     },
     resetPoints: () => {
-      webSocket?.send(JSON.stringify({ tag: "ResetPoints", content: null }));
+      connection?.send({ tag: "ResetPoints", content: null });
     },
     claimSession: () => {
-      webSocket?.send(JSON.stringify({ tag: "ClaimSession", content: null }));
+      connection?.send({ tag: "ClaimSession", content: null });
     },
     kickUser: (userId: string) => {
-      webSocket?.send(JSON.stringify({ tag: "KickUser", content: userId }));
+      connection?.send({ tag: "KickUser", content: userId });
     },
     setSpectator: (isSpectator: boolean) => {
-      webSocket?.send(JSON.stringify({ tag: "SetSpectator", content: isSpectator }));
+      connection?.send({ tag: "SetSpectator", content: isSpectator });
+    },
+    setSettings: (settings: SessionSettings) => {
+      connection?.send({ tag: "SetSettings", content: settings });
     },
     sessionId,
     userName,
@@ -139,62 +179,53 @@ export default function SessionControlProvider({ children }: SessionControlProvi
     if (sessionId === undefined) {
       return;
     }
-    const ws = new WebSocket(webSocketUrl(sessionId));
-    ws.onopen = (event) => {
-      console.log("WebSocket opened: ", event);
-      // Request the user id.
-      ws.send(JSON.stringify({tag: "Whoami", content: null }));
-      // Change the username. Also triggers a session broadcast.
-      ws.send(JSON.stringify({tag: "NameChange", content: userName }));
-      setWebSocket(ws);
-    };
-    ws.onerror = (event) => {
-      mc_notifications.showNotification({
-          message: "Session error: " + event,
-      });
-      console.log("WebSocket error: ", event);
-      ws.onclose = () => {};
-      ws.close();
-      sessionControl.resetSession();
-    };
-    ws.onclose = (event) => {
-      console.log("WebSocket closed: ", event);
-      ws.onclose = () => {};
-      ws.close();
-      sessionControl.resetSession();
-    };
-    ws.onmessage = (event) => {
-      const message = JSON.parse(event.data);
-      switch (message.tag) {
-        case "Error": {
-          ws.onclose = () => {};
-          ws.close();
-          sessionControl.resetSession();
-          mc_notifications.showNotification({
-            message: "Error: " + message.content,
-          });
-          break;
-        }
-        case "Whoami": {
-          setUid(message.content as string);
-          break;
-        }
-        case "State": {
-          setSessionState(message.content as SessionState);
-          break;
-        }
-        case "KeepAlive": {
-          break;
+    let current: Connection | undefined = undefined;
+    const handlers: ConnectionHandlers = {
+      onOpen: (opened) => {
+        // Request the user id.
+        opened.send({ tag: "Whoami", content: null });
+        // Change the username. Also triggers a session broadcast.
+        opened.send({ tag: "NameChange", content: userName });
+        setConnection(opened);
+      },
+      onMessage: (message) => {
+        switch (message.tag) {
+          case "Error": {
+            current?.close();
+            sessionControl.resetSession();
+            mc_notifications.showNotification({
+              message: "Error: " + String(message.content),
+            });
+            break;
+          }
+          case "Whoami": {
+            setUid(message.content as string);
+            break;
+          }
+          case "State": {
+            setSessionState(message.content as SessionState);
+            break;
+          }
+          case "KeepAlive": {
+            break;
+          }
+          default: {
+            throw new Error("Unexpected message tag: " + message.tag);
+          }
         }
-        default: {
-          throw new Error("Unexpected message tag: " + message.tag);
-        }
-      }
+      },
+      onClose: () => {
+        sessionControl.resetSession();
+      },
     };
+    current = openWebSocket(sessionId, handlers, () => {
+      // Proxies that break websockets usually pass Server-Sent Events.
+      console.log("WebSocket unavailable, falling back to Server-Sent Events");
+      current = openEventSource(sessionId, handlers);
+    });
     return () => {
-      console.log("Closing WebSocket")
-      ws.onclose = () => {};
-      ws.close();
+      console.log("Closing connection");
+      current?.close();
     };
   }, [sessionId]);
   return (
@@ -204,21 +235,100 @@ export default function SessionControlProvider({ children }: SessionControlProvi
   );
 }
 
+/**
+ * Connect to a session over a websocket. `onUnavailable` is called instead of `onClose` if the
+ * websocket closes before it was opened.
+ */
+function openWebSocket(
+  sessionId: string,
+  handlers: ConnectionHandlers,
+  onUnavailable: () => void,
+): Connection {
+  const ws = new WebSocket(webSocketUrl(sessionId));
+  let opened = false;
+  const connection: Connection = {
+    send: (message) => ws.send(JSON.stringify(message)),
+    close: () => {
+      ws.onclose = () => {};
+      ws.close();
+    },
+  };
+  ws.onopen = (event) => {
+    console.log("WebSocket opened: ", event);
+    opened = true;
+    handlers.onOpen(connection);
+  };
+  ws.onerror = (event) => {
+    console.log("WebSocket error: ", event);
+    if (opened) {
+      mc_notifications.showNotification({
+        message: "Session error: " + event,
+      });
+    }
+  };
+  ws.onclose = (event) => {
+    console.log("WebSocket closed: ", event);
+    connection.close();
+    if (opened) {
+      handlers.onClose();
+    } else {
+      onUnavailable();
+    }
+  };
+  ws.onmessage = (event) => handlers.onMessage(JSON.parse(event.data));
+  return connection;
+}
+
+/**
+ * Connect to a session with Server-Sent Events. The first event carries the token used to post
+ * messages.
+ */
+function openEventSource(sessionId: string, handlers: ConnectionHandlers): Connection {
+  const url = BASE_PATH + 'api/' + sessionId;
+  const events = new EventSource(url + '/events');
+  let token = '';
+  // Messages are posted one after another so the server receives them in order.
+  let queue: Promise<unknown> = Promise.resolve();
+  const connection: Connection = {
+    send: (message) => {
+      queue = queue
+        .then(() => fetch(url + '/messages', {
+          method: 'POST',
+          headers: {
+            'Authorization': 'Bearer ' + token,
+            'Content-Type': 'application/json',
+          },
+          body: JSON.stringify(message),
+        }))
+        .catch((error) => console.log("Posting message failed: ", error));
+    },
+    close: () => {
+      events.onerror = null;
+      events.close();
+    },
+  };
+  events.addEventListener('token', (event) => {
+    console.log("EventSource opened");
+    token = (event as MessageEvent).data;
+    handlers.onOpen(connection);
+  });
+  events.onmessage = (event) => handlers.onMessage(JSON.parse(event.data));
+  events.onerror = (event) => {
+    // EventSource reconnects on its own, but that would join the session as a new user.
+    console.log("EventSource error: ", event);
+    connection.close();
+    mc_notifications.showNotification({
+      message: "Session error: " + event,
+    });
+    handlers.onClose();
+  };
+  return connection;
+}
+
 function webSocketUrl(sessionId: string): string {
-  // Establish connection to session.
-  let url: string = '';
-  if (window.location.protocol === 'https:') {
-    url += 'wss://';
-  } else {
-    url += 'ws://';
-  }
-  url += window.location.hostname
-  if (window.location.port !== "") {
-    url += ':' + window.location.port;
-  }
-  url += '/api/';
-  url += sessionId;
-  return url;
+  // Establish connection to session under the base path the app is served from.
+  const protocol = window.location.protocol === 'https:' ? 'wss://' : 'ws://';
+  return protocol + window.location.host + BASE_PATH + 'api/' + sessionId;
 }
 
 export function useSessionControl(): SessionControl {
@@ -21,7 +21,10 @@ export function SessionPage() {
   const leaveSession = () => {
     sessionControl.resetSession();
   };
-  const selfState = sessionControl.sessionState.users[sessionControl.uid];
+  const selfState = sessionControl.sessionState.users.find((user) => user.id === sessionControl.uid);
+  if (selfState === undefined) {
+    return <mc.Loader />
+  }
 
   return (
     <>
@@ -29,7 +32,7 @@ export function SessionPage() {
     <h3>Users</h3>
     <UserTable />
     <h3>Cards</h3>
-    <Cards visible={selfState.points === null && !selfState.isSpectator} />
+    <Cards visible={selfState.voteStatus.tag === "NotVoted" && !selfState.isSpectator} />
     <h3>Statistics</h3>
     <Statistics />
     <h3>Controls</h3>